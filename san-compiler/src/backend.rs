@@ -0,0 +1,450 @@
+use std::fmt;
+use san_common::chunk::OpCode;
+use san_common::value::{FunctionData, Value};
+use crate::layout::{mouse_name, KeyboardLayout};
+use crate::media::{media_key_by_code, MediaKey};
+use crate::optimizer::{decode, Instruction};
+use crate::trace::{Trace, TraceEvent};
+
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum Backend {
+    SanVm,
+    Arduino,
+    CircuitPython,
+}
+
+impl Backend {
+    pub fn by_name(name: &str) -> Option<Backend> {
+        match name {
+            "sanvm" => Some(Backend::SanVm),
+            "arduino" => Some(Backend::Arduino),
+            "circuitpython" => Some(Backend::CircuitPython),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Backend::SanVm => "sanvm",
+            Backend::Arduino => "arduino",
+            Backend::CircuitPython => "circuitpython",
+        }
+    }
+
+    pub fn extension(&self) -> &'static str {
+        match self {
+            Backend::SanVm => "bin",
+            Backend::Arduino => "ino",
+            Backend::CircuitPython => "py",
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct LoweringError {
+    //None for problems found in the run instead of the source
+    pub line: Option<usize>,
+    pub message: String,
+}
+
+impl fmt::Display for LoweringError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.line {
+            Some(line) => write!(f, "line {}: {}", line, self.message),
+            None => write!(f, "{}", self.message),
+        }
+    }
+}
+
+//the backends replay a single run of the folded program, which is only the program itself when
+//nothing in it depends on a value known at run time, function calls are fine since they are inlined
+pub fn check_lowerable(function: &FunctionData) -> Vec<LoweringError> {
+    let mut errors = vec![];
+    collect_unlowerable(function, &mut errors);
+    errors.sort_by_key(|error| error.line);
+    //a for loop jumps back twice, once to its condition and once to its increment
+    errors.dedup();
+    errors
+}
+
+fn collect_unlowerable(function: &FunctionData, errors: &mut Vec<LoweringError>) {
+    let instructions = decode(&function.chunk);
+    for instruction in &instructions {
+        let message = match instruction.op {
+            OpCode::OpLoop(_) => {
+                //reported at the line the loop starts on, where its condition is
+                let start = instructions.iter().find(|other| Some(other.id) == instruction.target).unwrap_or(instruction);
+                errors.push(LoweringError { line: Some(start.line), message: String::from("Loops can't be lowered") });
+                None
+            }
+            OpCode::OpJumpIfFalse(_) | OpCode::OpJumpIfTrue(_) if !exits_loop(&instructions, instruction) => {
+                Some("Conditions that aren't known at compile time can't be lowered")
+            }
+            _ => None,
+        };
+        if let Some(message) = message {
+            errors.push(LoweringError { line: Some(instruction.line), message: message.to_string() });
+        }
+        if let Some(Value::ValFunction(nested)) = &instruction.constant {
+            collect_unlowerable(nested, errors);
+        }
+    }
+}
+
+//the exit jump of a loop lands right after its closing OpLoop, the loop itself is already reported
+fn exits_loop(instructions: &[Instruction], jump: &Instruction) -> bool {
+    let landing = instructions.iter().position(|other| Some(other.id) == jump.target);
+    match landing {
+        Some(index) if index > 0 => matches!(instructions[index - 1].op, OpCode::OpLoop(_)),
+        _ => false,
+    }
+}
+
+pub fn arduino_sketch(trace: &Trace, layout: &KeyboardLayout, source_name: &str) -> Result<String, LoweringError> {
+    let mut sketch = ArduinoSketch { lines: vec![] };
+    lower(trace, layout, &mut sketch)?;
+    let uses_mouse = uses_mouse(trace);
+
+    let mut out = format!("// Generated by sanc from {}, do not edit\n#include <Keyboard.h>\n", source_name);
+    if uses_mouse {
+        out.push_str("#include <Mouse.h>\n");
+    }
+    out.push_str(
+        "
+// Keyboard.h takes modifiers as 128 + their bit and any other key as 136 + its HID usage id
+void press(uint8_t code) {
+  if (code >= 0xE0 && code <= 0xE7) {
+    Keyboard.press(128 + code - 0xE0);
+  } else {
+    Keyboard.press(136 + code);
+  }
+}
+
+void release(uint8_t code) {
+  if (code >= 0xE0 && code <= 0xE7) {
+    Keyboard.release(128 + code - 0xE0);
+  } else {
+    Keyboard.release(136 + code);
+  }
+}
+",
+    );
+    if uses_mouse {
+        out.push_str(
+            "
+// Mouse.move moves at most 127 on each axis
+void moveMouse(long x, long y) {
+  while (x != 0 || y != 0) {
+    long stepX = constrain(x, -127, 127);
+    long stepY = constrain(y, -127, 127);
+    Mouse.move(stepX, stepY, 0);
+    x -= stepX;
+    y -= stepY;
+  }
+}
+",
+        );
+    }
+
+    out.push_str("\nvoid setup() {\n  Keyboard.begin();\n");
+    if uses_mouse {
+        out.push_str("  Mouse.begin();\n");
+    }
+    if uses_print(trace) {
+        out.push_str("  Serial.begin(9600);\n");
+    }
+    for line in &sketch.lines {
+        out.push_str(format!("  {}\n", line).as_str());
+    }
+    out.push_str("  Keyboard.end();\n}\n\nvoid loop() {\n}\n");
+    Ok(out)
+}
+
+pub fn circuitpython_script(trace: &Trace, layout: &KeyboardLayout, source_name: &str) -> Result<String, LoweringError> {
+    let mut script = CircuitPythonScript { lines: vec![] };
+    lower(trace, layout, &mut script)?;
+    let uses_mouse = uses_mouse(trace);
+
+    let mut out = format!(
+        "# Generated by sanc from {}, do not edit\nimport time\nimport usb_hid\nfrom adafruit_hid.keyboard import Keyboard\n",
+        source_name
+    );
+    let uses_media = trace.events.iter().any(|event| matches!(event, TraceEvent::Media(_)));
+    if uses_mouse {
+        out.push_str("from adafruit_hid.mouse import Mouse\n");
+    }
+    if uses_media {
+        out.push_str("from adafruit_hid.consumer_control import ConsumerControl\n");
+    }
+    out.push_str("\nkeyboard = Keyboard(usb_hid.devices)\n");
+    if uses_mouse {
+        out.push_str("mouse = Mouse(usb_hid.devices)\n");
+    }
+    if uses_media {
+        out.push_str("consumer_control = ConsumerControl(usb_hid.devices)\n");
+    }
+    out.push('\n');
+    for line in &script.lines {
+        out.push_str(format!("{}\n", line).as_str());
+    }
+    Ok(out)
+}
+
+fn uses_mouse(trace: &Trace) -> bool {
+    trace.events.iter().any(|event| matches!(
+        event,
+        TraceEvent::MouseMove(..) | TraceEvent::MouseDown(_) | TraceEvent::MouseUp
+            | TraceEvent::MouseScroll(..) | TraceEvent::MouseClick(_) | TraceEvent::MouseMoveTo(..)
+    ))
+}
+
+fn uses_print(trace: &Trace) -> bool {
+    trace.events.iter().any(|event| matches!(event, TraceEvent::Print(_)))
+}
+
+#[derive(Copy, Clone, PartialEq)]
+enum MouseButton {
+    Left,
+    Right,
+    Middle,
+}
+
+//the statements a backend writes for each kind of trace event
+trait Emitter {
+    fn press(&mut self, codes: &[u8], comment: String);
+    fn release(&mut self, codes: &[u8]);
+    fn release_all(&mut self);
+    fn delay(&mut self, ms: u64);
+    fn mouse_move(&mut self, x: i64, y: i64);
+    fn mouse_press(&mut self, button: MouseButton);
+    fn mouse_release(&mut self, button: MouseButton);
+    fn mouse_scroll(&mut self, steps: i64);
+    fn mouse_click(&mut self, button: MouseButton);
+    fn media(&mut self, key: &MediaKey) -> Result<(), LoweringError>;
+    fn print(&mut self, text: &str);
+}
+
+//key reports are states, so each one is lowered to the keys pressed and released since the last report
+fn lower(trace: &Trace, layout: &KeyboardLayout, emitter: &mut dyn Emitter) -> Result<(), LoweringError> {
+    let mut held: Vec<u8> = vec![];
+    let mut buttons: Vec<MouseButton> = vec![];
+    //back to back delays, like the one after the last key of a sequence and a sleep, become one
+    let mut delay = 0;
+
+    for event in &trace.events {
+        if let TraceEvent::Delay(ms) = event {
+            delay += ms;
+            continue;
+        }
+        if delay > 0 {
+            emitter.delay(std::mem::take(&mut delay));
+        }
+        match event {
+            TraceEvent::KeyReport(keys) if keys.is_empty() => {
+                if !held.is_empty() {
+                    emitter.release_all();
+                    held.clear();
+                }
+            }
+            TraceEvent::KeyReport(keys) => {
+                let released: Vec<u8> = held.iter().filter(|code| !keys.contains(code)).copied().collect();
+                if !released.is_empty() {
+                    emitter.release(&released);
+                }
+                let pressed: Vec<u8> = keys.iter().filter(|code| !held.contains(code)).copied().collect();
+                if !pressed.is_empty() {
+                    emitter.press(&pressed, describe(keys, layout));
+                }
+                held = keys.clone();
+            }
+            TraceEvent::Delay(_) => (),
+            TraceEvent::MouseMove(x, y) => emitter.mouse_move(*x, *y),
+            TraceEvent::MouseDown(code) => {
+                let button = mouse_button(*code)?;
+                emitter.mouse_press(button);
+                buttons.push(button);
+            }
+            TraceEvent::MouseUp => {
+                for button in buttons.drain(..) {
+                    emitter.mouse_release(button);
+                }
+            }
+            //both libraries only have the relative mouse and a vertical wheel
+            TraceEvent::MouseScroll(_, horizontal) if *horizontal != 0 => {
+                return Err(LoweringError { line: None, message: String::from("Horizontal scrolling can't be lowered, the mouse only has a vertical wheel") });
+            }
+            TraceEvent::MouseScroll(vertical, _) => emitter.mouse_scroll(*vertical),
+            TraceEvent::MouseClick(code) => emitter.mouse_click(mouse_button(*code)?),
+            TraceEvent::MouseMoveTo(..) => {
+                return Err(LoweringError { line: None, message: String::from("mouse_move_to can't be lowered, the mouse only moves relative to where it is") });
+            }
+            TraceEvent::Media(code) => {
+                let key = media_key_by_code(*code)
+                    .ok_or_else(|| LoweringError { line: None, message: format!("Unknown media key 0x{:06X}", code) })?;
+                emitter.media(key)?;
+            }
+            TraceEvent::Print(text) => emitter.print(text),
+        }
+    }
+    if delay > 0 {
+        emitter.delay(delay);
+    }
+    Ok(())
+}
+
+fn mouse_button(code: u8) -> Result<MouseButton, LoweringError> {
+    let name = mouse_name(code).unwrap_or("");
+    if name.starts_with("LEFT") {
+        Ok(MouseButton::Left)
+    } else if name.starts_with("RIGHT") {
+        Ok(MouseButton::Right)
+    } else if name.starts_with("MIDDLE") {
+        Ok(MouseButton::Middle)
+    } else {
+        Err(LoweringError { line: None, message: format!("Mouse button 0x{:02X} can't be lowered", code) })
+    }
+}
+
+//comment next to a press, the typed character or the names of a shortcut
+fn describe(keys: &[u8], layout: &KeyboardLayout) -> String {
+    match Trace::render_chord(keys, layout) {
+        Some('\n') => String::from("ENTER"),
+        Some('\t') => String::from("TAB"),
+        Some(' ') => String::from("space"),
+        Some(c) => format!("'{}'", c),
+        None => Trace::chord_names(keys),
+    }
+}
+
+fn hex(code: &u8) -> String {
+    format!("0x{:02X}", code)
+}
+
+fn quote(text: &str) -> String {
+    format!("\"{}\"", text.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n"))
+}
+
+struct ArduinoSketch {
+    lines: Vec<String>,
+}
+
+impl Emitter for ArduinoSketch {
+    fn press(&mut self, codes: &[u8], comment: String) {
+        let presses: Vec<String> = codes.iter().map(|code| format!("press({});", hex(code))).collect();
+        self.lines.push(format!("{} // {}", presses.join(" "), comment));
+    }
+
+    fn release(&mut self, codes: &[u8]) {
+        let releases: Vec<String> = codes.iter().map(|code| format!("release({});", hex(code))).collect();
+        self.lines.push(releases.join(" "));
+    }
+
+    fn release_all(&mut self) {
+        self.lines.push(String::from("Keyboard.releaseAll();"));
+    }
+
+    fn delay(&mut self, ms: u64) {
+        self.lines.push(format!("delay({});", ms));
+    }
+
+    fn mouse_move(&mut self, x: i64, y: i64) {
+        self.lines.push(format!("moveMouse({}, {});", x, y));
+    }
+
+    fn mouse_press(&mut self, button: MouseButton) {
+        self.lines.push(format!("Mouse.press({});", arduino_button(button)));
+    }
+
+    fn mouse_release(&mut self, button: MouseButton) {
+        self.lines.push(format!("Mouse.release({});", arduino_button(button)));
+    }
+
+    fn mouse_scroll(&mut self, steps: i64) {
+        self.lines.push(format!("Mouse.move(0, 0, {});", steps));
+    }
+
+    fn mouse_click(&mut self, button: MouseButton) {
+        self.lines.push(format!("Mouse.click({});", arduino_button(button)));
+    }
+
+    fn media(&mut self, key: &MediaKey) -> Result<(), LoweringError> {
+        Err(LoweringError { line: None, message: format!("Keyboard.h can't send media keys, {} can't be lowered", key.name) })
+    }
+
+    fn print(&mut self, text: &str) {
+        self.lines.push(format!("Serial.println({});", quote(text)));
+    }
+}
+
+fn arduino_button(button: MouseButton) -> &'static str {
+    match button {
+        MouseButton::Left => "MOUSE_LEFT",
+        MouseButton::Right => "MOUSE_RIGHT",
+        MouseButton::Middle => "MOUSE_MIDDLE",
+    }
+}
+
+struct CircuitPythonScript {
+    lines: Vec<String>,
+}
+
+impl Emitter for CircuitPythonScript {
+    fn press(&mut self, codes: &[u8], comment: String) {
+        let codes: Vec<String> = codes.iter().map(hex).collect();
+        self.lines.push(format!("keyboard.press({})  # {}", codes.join(", "), comment));
+    }
+
+    fn release(&mut self, codes: &[u8]) {
+        let codes: Vec<String> = codes.iter().map(hex).collect();
+        self.lines.push(format!("keyboard.release({})", codes.join(", ")));
+    }
+
+    fn release_all(&mut self) {
+        self.lines.push(String::from("keyboard.release_all()"));
+    }
+
+    fn delay(&mut self, ms: u64) {
+        self.lines.push(format!("time.sleep({})", ms as f64 / 1000.0));
+    }
+
+    fn mouse_move(&mut self, x: i64, y: i64) {
+        self.lines.push(format!("mouse.move(x={}, y={})", x, y));
+    }
+
+    fn mouse_press(&mut self, button: MouseButton) {
+        self.lines.push(format!("mouse.press({})", circuitpython_button(button)));
+    }
+
+    fn mouse_release(&mut self, button: MouseButton) {
+        self.lines.push(format!("mouse.release({})", circuitpython_button(button)));
+    }
+
+    fn mouse_scroll(&mut self, steps: i64) {
+        self.lines.push(format!("mouse.move(wheel={})", steps));
+    }
+
+    fn mouse_click(&mut self, button: MouseButton) {
+        self.lines.push(format!("mouse.click({})", circuitpython_button(button)));
+    }
+
+    //adafruit_hid only has a device for the consumer page
+    fn media(&mut self, key: &MediaKey) -> Result<(), LoweringError> {
+        if key.is_system() {
+            return Err(LoweringError { line: None, message: format!("adafruit_hid can't send system control keys, {} can't be lowered", key.name) });
+        }
+        self.lines.push(format!("consumer_control.send(0x{:02X})  # {}", key.usage, key.name));
+        Ok(())
+    }
+
+    fn print(&mut self, text: &str) {
+        self.lines.push(format!("print({})", quote(text)));
+    }
+}
+
+fn circuitpython_button(button: MouseButton) -> &'static str {
+    match button {
+        MouseButton::Left => "Mouse.LEFT_BUTTON",
+        MouseButton::Right => "Mouse.RIGHT_BUTTON",
+        MouseButton::Middle => "Mouse.MIDDLE_BUTTON",
+    }
+}
@@ -1,14 +1,29 @@
+use crate::diagnostic::Diagnostic;
+use crate::flow::reachable;
+use crate::lint::{AllowedLints, DeclarationKind, MISSING_RETURN, REDECLARATION, SHADOWING, UNREACHABLE, UNUSED};
+use crate::media::media_key;
+use crate::modules::{Module, ModuleLoader, SourceFile};
+use crate::natives::{check_constant_arguments, native_by_name, suggest_native};
+use crate::optimizer::{append, constant_value, decode, fold_constants};
+use crate::params::{Params, CONFIG_FILE, TARGETS, TARGET_PARAM};
 use crate::parser::Parser;
 use crate::scanner::Scanner;
 use crate::token::{Token, TokenType};
+use crate::types::{binary_type, Signature, Type};
+use crate::typing::TypingProfile;
 use crate::ScannerRef;
 use num_derive::FromPrimitive;
 use san_common::chunk::OpCode::OpConstant;
 use san_common::chunk::{Chunk, OpCode};
 use san_common::value::{FunctionData, FunctionType, Number, Value};
 use std::cell::RefCell;
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
 use std::isize;
 use std::rc::Rc;
+use std::sync::Arc;
 use strum::EnumCount;
 use san_common::keycodes::{hid_string_to_code, mouse_string_to_code};
 
@@ -52,6 +67,34 @@ struct ParseRule<'a> {
 pub struct Local {
     token: Token,
     depth: isize,
+    ty: Type,
+    //only annotated variables keep their type on assignment, the others take whatever they are given
+    annotated: bool,
+    kind: DeclarationKind,
+    used: bool,
+}
+
+struct GlobalDeclaration {
+    name: String,
+    token: Token,
+    kind: DeclarationKind,
+    used: bool,
+}
+
+//a compile-time constant, inlined wherever it is read instead of taking a variable slot
+#[derive(Clone)]
+pub struct Constant {
+    name: String,
+    token: Token,
+    depth: isize,
+    value: Value,
+    ty: Type,
+    used: bool,
+}
+
+pub struct TestCase {
+    pub name: String,
+    pub function: FunctionData,
 }
 
 pub struct Compiler<'a> {
@@ -63,6 +106,43 @@ pub struct Compiler<'a> {
     source: &'a str,
     locals: Vec<Local>,
     scope_depth: isize,
+    include_tests: bool,
+    in_test: bool,
+    tests: Vec<TestCase>,
+    //a trailing expression without `;` becomes the return value of the script
+    repl: bool,
+    //names of the top level variables and functions, carried from one REPL entry to the next
+    globals: Vec<String>,
+    //types of the values the emitted code leaves on the stack while an expression is compiled
+    types: Vec<Type>,
+    //declared or inferred types of the top level variables and functions
+    global_types: HashMap<String, Type>,
+    //top level variables declared with a type annotation
+    annotated_globals: HashSet<String>,
+    return_type: Type,
+    global_declarations: Vec<GlobalDeclaration>,
+    //globals read by a nested function, handed to the enclosing compiler once the function is done
+    global_reads: Vec<String>,
+    //reads of globals that were not declared yet at that point, like calls to functions further down
+    forward_reads: HashSet<String>,
+    //offset and first token of every statement, checked for reachability once the function is done
+    statements: Vec<(usize, Token)>,
+    returns_value: bool,
+    //slot and scope depth of the keys of every `hold` block around the current statement
+    holds: Vec<(usize, isize)>,
+    constants: Vec<Constant>,
+    params: Params,
+    modules: Rc<RefCell<ModuleLoader>>,
+    //path of the compiled file, imports are resolved relative to it
+    path: Option<PathBuf>,
+    //prefix of the globals when an imported file is compiled
+    namespace: Option<String>,
+    //top level names of the imported file that is being compiled
+    module_globals: HashSet<String>,
+    //modules imported by the file, by the name given with `as`
+    aliases: HashMap<String, Module>,
+    //reads of `target`, an `if` whose condition reads it is decided at compile time
+    target_reads: usize,
 }
 
 impl<'a> Compiler<'a> {
@@ -112,6 +192,7 @@ impl<'a> Compiler<'a> {
         add_table_entry!(TokenType::LeftBrace, None, None, Precedence::None);
         add_table_entry!(TokenType::RightBrace, None, None, Precedence::None);
         add_table_entry!(TokenType::Comma, None, None, Precedence::None);
+        add_table_entry!(TokenType::Colon, None, None, Precedence::None);
         add_table_entry!(TokenType::Dot, None, None, Precedence::None);
         add_table_entry!(
             TokenType::Minus,
@@ -187,6 +268,10 @@ impl<'a> Compiler<'a> {
             Some(Compiler::binary),
             Precedence::Comparison
         );
+        add_table_entry!(TokenType::Arrow, None, None, Precedence::None);
+        add_table_entry!(TokenType::Hash, None, None, Precedence::None);
+        add_table_entry!(TokenType::LeftBracket, None, None, Precedence::None);
+        add_table_entry!(TokenType::RightBracket, None, None, Precedence::None);
         add_table_entry!(
             TokenType::Identifier,
             Some(Compiler::variable),
@@ -217,7 +302,15 @@ impl<'a> Compiler<'a> {
             None,
             Precedence::None
         );
+        add_table_entry!(
+            TokenType::MediaKey,
+            Some(Compiler::media_key),
+            None,
+            Precedence::None
+        );
         add_table_entry!(TokenType::And, None, Some(Compiler::and), Precedence::And);
+        add_table_entry!(TokenType::Assert, None, None, Precedence::None);
+        add_table_entry!(TokenType::Const, None, None, Precedence::None);
         add_table_entry!(TokenType::Else, None, None, Precedence::None);
         add_table_entry!(
             TokenType::False,
@@ -227,6 +320,7 @@ impl<'a> Compiler<'a> {
         );
         add_table_entry!(TokenType::For, None, None, Precedence::None);
         add_table_entry!(TokenType::Fn, None, None, Precedence::None);
+        add_table_entry!(TokenType::Hold, None, None, Precedence::None);
         add_table_entry!(TokenType::If, None, None, Precedence::None);
         add_table_entry!(TokenType::Key, None, None, Precedence::None);
         add_table_entry!(TokenType::Match, None, None, Precedence::None);
@@ -238,16 +332,35 @@ impl<'a> Compiler<'a> {
             Precedence::None
         );
         add_table_entry!(TokenType::Or, None, Some(Compiler::or), Precedence::Or);
+        add_table_entry!(
+            TokenType::Param,
+            Some(Compiler::param),
+            None,
+            Precedence::None
+        );
         add_table_entry!(TokenType::Print, None, None, Precedence::None);
         add_table_entry!(TokenType::Return, None, None, Precedence::None);
+        add_table_entry!(
+            TokenType::Target,
+            Some(Compiler::target),
+            None,
+            Precedence::None
+        );
+        add_table_entry!(TokenType::Test, None, None, Precedence::None);
         add_table_entry!(
             TokenType::True,
             Some(Compiler::literal),
             None,
             Precedence::None
         );
+        add_table_entry!(TokenType::Typing, None, None, Precedence::None);
         add_table_entry!(TokenType::Let, None, None, Precedence::None);
+        add_table_entry!(TokenType::Import, None, None, Precedence::None);
+        add_table_entry!(TokenType::As, None, None, Precedence::None);
         add_table_entry!(TokenType::While, None, None, Precedence::None);
+        add_table_entry!(TokenType::Whitespace, None, None, Precedence::None);
+        add_table_entry!(TokenType::Newline, None, None, Precedence::None);
+        add_table_entry!(TokenType::Comment, None, None, Precedence::None);
         let error_token = TokenType::Error("".to_string());
         add_table_entry!(error_token, None, None, Precedence::None);
         add_table_entry!(TokenType::EOF, None, None, Precedence::None);
@@ -270,12 +383,35 @@ impl<'a> Compiler<'a> {
             source,
             locals: vec![],
             scope_depth: 0,
+            include_tests: false,
+            in_test: false,
+            tests: vec![],
+            repl: false,
+            globals: vec![],
+            types: vec![],
+            global_types: HashMap::new(),
+            annotated_globals: HashSet::new(),
+            return_type: Type::Any,
+            global_declarations: vec![],
+            global_reads: vec![],
+            forward_reads: HashSet::new(),
+            statements: vec![],
+            returns_value: false,
+            holds: vec![],
+            constants: vec![],
+            params: Params::new(),
+            modules: Rc::new(RefCell::new(ModuleLoader::new())),
+            path: None,
+            namespace: None,
+            module_globals: HashSet::new(),
+            aliases: HashMap::new(),
+            target_reads: 0,
         };
 
         if function_type != FunctionType::Script {
             compiler.function.name = compiler.parser.previous.as_ref().unwrap().get_token_string(compiler.source);
         }
-        compiler.locals.push(Local { depth: 0, token: Token::new(TokenType::Nil, 0, 0, 0) });
+        compiler.locals.push(Local { depth: 0, token: Token::new(TokenType::Nil, 0, 0, 0), ty: Type::Any, annotated: false, kind: DeclarationKind::Variable, used: true });
         Compiler::token_table_init(&mut compiler);
         compiler
     }
@@ -297,16 +433,98 @@ impl<'a> Compiler<'a> {
             source,
             locals: vec![],
             scope_depth: 0,
+            include_tests: false,
+            in_test: false,
+            tests: vec![],
+            repl: false,
+            globals: vec![],
+            types: vec![],
+            global_types: HashMap::new(),
+            annotated_globals: HashSet::new(),
+            return_type: Type::Any,
+            global_declarations: vec![],
+            global_reads: vec![],
+            forward_reads: HashSet::new(),
+            statements: vec![],
+            returns_value: false,
+            holds: vec![],
+            constants: vec![],
+            params: Params::new(),
+            modules: Rc::new(RefCell::new(ModuleLoader::new())),
+            path: None,
+            namespace: None,
+            module_globals: HashSet::new(),
+            aliases: HashMap::new(),
+            target_reads: 0,
         };
 
         if function_type != FunctionType::Script {
             compiler.function.name = compiler.parser.previous.as_ref().unwrap().get_token_string(compiler.source);
         }
-        compiler.locals.push(Local { depth: 0, token: Token::new(TokenType::Nil, 0, 0, 0) });
+        compiler.locals.push(Local { depth: 0, token: Token::new(TokenType::Nil, 0, 0, 0), ty: Type::Any, annotated: false, kind: DeclarationKind::Variable, used: true });
         Compiler::token_table_init(&mut compiler);
         compiler
     }
 
+    //test blocks are always compiled for errors, but only kept when this is set
+    pub fn set_include_tests(&mut self, include_tests: bool) {
+        self.include_tests = include_tests;
+    }
+
+    pub fn tests(&self) -> &[TestCase] {
+        &self.tests
+    }
+
+    pub fn set_repl(&mut self, repl: bool) {
+        self.repl = repl;
+    }
+
+    //globals defined by earlier compilations that share the same environment
+    pub fn set_globals(&mut self, globals: Vec<String>) {
+        self.globals = globals;
+    }
+
+    pub fn globals(&self) -> &[String] {
+        &self.globals
+    }
+
+    //top level constants of earlier compilations, they are not stored in the globals
+    pub fn set_constants(&mut self, constants: Vec<Constant>) {
+        self.constants = constants;
+    }
+
+    pub fn constants(&self) -> &[Constant] {
+        &self.constants
+    }
+
+    pub fn set_path(&mut self, path: &Path) {
+        self.path = Some(fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf()));
+    }
+
+    //directories searched for imports that are not found next to the importing file
+    pub fn add_search_path(&mut self, path: PathBuf) {
+        self.modules.borrow_mut().add_search_path(path);
+    }
+
+    //path and source of an imported file, for rendering its diagnostics
+    pub fn source_file(&self, file: usize) -> Option<SourceFile> {
+        self.modules.borrow().file(file).cloned()
+    }
+
+    //values for `param(...)`, from -D flags and the config of the payload
+    pub fn set_params(&mut self, params: Params) {
+        self.params = params;
+    }
+
+    //collects diagnostics without printing them, for tools like the language server
+    pub fn set_quiet(&mut self, quiet: bool) {
+        self.parser.quiet = quiet;
+    }
+
+    pub fn diagnostics(&self) -> &[Diagnostic] {
+        &self.parser.diagnostics
+    }
+
     fn get_chunk(&self) -> &Chunk {
         &self
             .function.chunk
@@ -318,39 +536,319 @@ impl<'a> Compiler<'a> {
     }
 
     pub fn compile(&mut self) -> Option<FunctionData> {
+        for name in Compiler::top_level_functions(self.source, self.params.target()) {
+            if self.namespace.is_some() {
+                self.module_globals.insert(name.clone());
+            }
+            let global = self.global_name(name.as_str());
+            self.define_global(global.as_str());
+        }
+        self.parser.allowed = AllowedLints::from_source(self.source);
+        if let Some(path) = &self.path {
+            self.modules.borrow_mut().begin_loading(path.clone());
+        }
         self.parser.advance(self.scanner.clone());
+        //the [typing] table of the config applies to the program, not to every module or REPL entry
+        if let Some(profile) = self.params.typing.filter(|_| self.namespace.is_none() && !self.repl) {
+            self.emit_typing_profile(profile);
+        }
 
         while !self.match_token(TokenType::EOF) {
             self.declaration();
         }
+        if self.path.is_some() {
+            self.modules.borrow_mut().end_loading();
+        }
+        //a REPL entry declares globals for the entries that follow it, a module for the files importing it
+        if !self.repl && self.namespace.is_none() {
+            self.report_unused_globals();
+        }
 
         let had_error = self.parser.had_error;
         let function = self.end_compiler();
         if !had_error { Some(function) } else { None }
     }
 
+    //functions are declared up front, so they can be called before their declaration
+    fn top_level_functions(source: &str, target: Option<&str>) -> Vec<String> {
+        let mut scanner = Scanner::new(source);
+        let mut functions = vec![];
+        let mut previous = TokenType::EOF;
+        let mut depth = 0;
+        //functions of other targets don't exist, a `#[target(...)]` applies up to the end of the next declaration
+        let mut attribute = None;
+        let mut enabled = true;
+
+        loop {
+            let token = scanner.scan_token();
+            match token.token_type {
+                TokenType::EOF => return functions,
+                TokenType::Hash if depth == 0 => attribute = Some(vec![]),
+                TokenType::Identifier if attribute.is_some() => {
+                    if let Some(targets) = attribute.as_mut() {
+                        targets.push(token.get_token_string(source));
+                    }
+                }
+                TokenType::RightBracket => {
+                    if let (Some(targets), Some(target)) = (attribute.take(), target) {
+                        enabled &= targets.iter().any(|name| name == target);
+                    }
+                }
+                TokenType::LeftBrace => depth += 1,
+                TokenType::RightBrace => {
+                    depth -= 1;
+                    if depth == 0 {
+                        enabled = true;
+                    }
+                }
+                TokenType::Semicolon if depth == 0 => enabled = true,
+                TokenType::Identifier if previous == TokenType::Fn && depth == 0 => {
+                    if enabled {
+                        functions.push(token.get_token_string(source));
+                    }
+                    enabled = true;
+                }
+                _ => ()
+            }
+            previous = token.token_type;
+        }
+    }
+
     fn declaration(&mut self) {
+        self.types.clear();
+        if self.match_token(TokenType::Hash) && !self.attributes() {
+            self.skip_declaration();
+            return;
+        }
+        self.mark_statement();
         if self.match_token(TokenType::Fn) {
             self.fn_declaration();
+        } else if self.match_token(TokenType::Test) {
+            self.test_declaration();
         } else if self.match_token(TokenType::Let) {
             self.variable_declaration();
+        } else if self.match_token(TokenType::Const) {
+            self.const_declaration();
+        } else if self.match_token(TokenType::Import) {
+            self.import_declaration();
         } else {
             self.statement();
         }
     }
 
+    //`#[target(windows, linux)]` keeps the declaration after it only when compiling for one of the listed targets
+    fn attributes(&mut self) -> bool {
+        let mut enabled = true;
+        loop {
+            self.parser.consume(TokenType::LeftBracket, String::from("Expect '[' after '#'"), self.scanner.clone());
+            self.parser.consume(TokenType::Target, String::from("Expect attribute name, only 'target' is supported"), self.scanner.clone());
+            let keyword = self.previous_token();
+            self.parser.consume(TokenType::LeftParen, String::from("Expect '(' after 'target'"), self.scanner.clone());
+            let mut targets = vec![];
+            loop {
+                self.parser.consume(TokenType::Identifier, String::from("Expect target name"), self.scanner.clone());
+                let name = self.previous_token();
+                let target = name.get_token_string(self.source);
+                if !TARGETS.contains(&target.as_str()) {
+                    self.parser.error_at_token(&name, format!("Unknown target '{}', expected one of {}", target, TARGETS.join(", ")), self.source);
+                }
+                targets.push(target);
+                if !self.match_token(TokenType::Comma) {
+                    break;
+                }
+            }
+            self.parser.consume(TokenType::RightParen, String::from("Expect ')' after targets"), self.scanner.clone());
+            self.parser.consume(TokenType::RightBracket, String::from("Expect ']' after attribute"), self.scanner.clone());
+
+            if let Some(target) = self.target_name(&keyword) {
+                enabled &= targets.contains(&target);
+            }
+            if !self.match_token(TokenType::Hash) {
+                return enabled;
+            }
+        }
+    }
+
+    //code of other targets is skipped without being compiled, it may use names that only exist on its target
+    fn skip_declaration(&mut self) {
+        let braced = [TokenType::Fn, TokenType::Test, TokenType::LeftBrace, TokenType::If, TokenType::While, TokenType::For, TokenType::Typing, TokenType::Hold]
+            .iter()
+            .any(|token_type| self.check_token(token_type.clone()));
+        let mut depth = 0;
+        while !self.check_token(TokenType::EOF) {
+            self.parser.advance(self.scanner.clone());
+            let ended = match self.previous_token().token_type {
+                TokenType::LeftParen | TokenType::LeftBrace => {
+                    depth += 1;
+                    false
+                }
+                TokenType::RightParen => {
+                    depth -= 1;
+                    false
+                }
+                TokenType::RightBrace => {
+                    depth -= 1;
+                    braced && depth == 0
+                }
+                TokenType::Semicolon => depth == 0,
+                _ => false,
+            };
+            if ended && !self.check_token(TokenType::Else) {
+                return;
+            }
+        }
+    }
+
     fn fn_declaration(&mut self) {
         let global = self.parse_variable("Expect function name");
+        let name = self.previous_token();
+        self.declare_kind(&name, DeclarationKind::Function);
         self.mark_initialized();
         self.function(FunctionType::Function);
+        let function_type = self.pop_type();
+        self.set_variable_type(&name, function_type, false);
         self.define_variable(global);
     }
 
+    fn test_declaration(&mut self) {
+        if self.function_type != FunctionType::Script || self.scope_depth > 0 {
+            self.parser.error(String::from("Test blocks are only allowed at top level"), self.source);
+        }
+
+        self.parser.consume(TokenType::String, String::from("Expect test name"), self.scanner.clone());
+        let name = self
+            .parser
+            .previous
+            .as_ref()
+            .expect("Parser does not have processed token!")
+            .get_token_string(self.source);
+        let name = name.trim_matches('"').to_string();
+
+        let mut compiler = Compiler::new_from_existing(self.parser.clone(), self.scanner.clone(), self.source, FunctionType::Function);
+        compiler.function.name = name.clone();
+        compiler.in_test = true;
+        self.share_globals(&mut compiler);
+        compiler.begin_scope();
+        compiler.parser.consume(TokenType::LeftBrace, String::from("Expect '{' before test body"), self.scanner.clone());
+        compiler.block();
+        compiler.report_unused_locals(1);
+
+        let function = compiler.end_compiler();
+        for name in std::mem::take(&mut compiler.global_reads) {
+            self.record_global_read(name.as_str());
+        }
+        self.parser = compiler.parser;
+        self.scanner = compiler.scanner;
+
+        if self.include_tests {
+            self.tests.push(TestCase { name, function });
+        }
+    }
+
+    fn import_declaration(&mut self) {
+        if self.function_type != FunctionType::Script || self.scope_depth > 0 {
+            self.parser.error(String::from("Imports are only allowed at top level"), self.source);
+        }
+
+        self.parser.consume(TokenType::String, String::from("Expect module path after 'import'"), self.scanner.clone());
+        let path_token = self.previous_token();
+        let import = path_token.get_token_string(self.source).trim_matches('"').to_string();
+        self.parser.consume(TokenType::As, String::from("Expect 'as' after module path"), self.scanner.clone());
+        self.parser.consume(TokenType::Identifier, String::from("Expect module name after 'as'"), self.scanner.clone());
+        let alias = self.previous_token();
+        let alias_name = alias.get_token_string(self.source);
+        self.parser.consume(TokenType::Semicolon, String::from("Expect ';' after import"), self.scanner.clone());
+
+        if self.aliases.contains_key(&alias_name) || self.is_global(self.global_name(alias_name.as_str()).as_str()) {
+            self.parser.error_at_token(&alias, format!("'{}' is already declared", alias_name), self.source);
+            return;
+        }
+
+        let resolved = self.modules.borrow().resolve(import.as_str(), self.path.as_deref());
+        let path = match resolved {
+            Some(path) => path,
+            None => {
+                self.parser.error_at_token(&path_token, format!("Can't find module '{}'", import), self.source);
+                return;
+            }
+        };
+
+        let cycle = self.modules.borrow().cycle(&path);
+        if let Some(cycle) = cycle {
+            self.parser.error_at_token(&path_token, format!("Import cycle: {}", cycle), self.source);
+            return;
+        }
+
+        let loaded = self.modules.borrow().module(&path).cloned();
+        let module = match loaded {
+            Some(module) => module,
+            None => match self.load_module(&path_token, import.as_str(), path) {
+                Some(module) => module,
+                None => return,
+            }
+        };
+        self.aliases.insert(alias_name, module);
+    }
+
+    //compiles an imported file into a function that defines its globals, called where the file is first imported
+    fn load_module(&mut self, path_token: &Token, import: &str, path: PathBuf) -> Option<Module> {
+        let read = self.modules.borrow().read(&path);
+        let source = match read {
+            Ok(source) => source,
+            Err(e) => {
+                self.parser.error_at_token(path_token, format!("Can't read module '{}': {}", import, e), self.source);
+                return None;
+            }
+        };
+        let (file, prefix) = {
+            let mut modules = self.modules.borrow_mut();
+            (modules.add_file(path.clone(), source.clone()), modules.reserve_prefix(&path))
+        };
+
+        let mut compiler = Compiler::new(source.as_str(), FunctionType::Script);
+        compiler.scanner.borrow_mut().set_file(file);
+        compiler.parser.quiet = self.parser.quiet;
+        compiler.parser.file_name = Some(path.display().to_string());
+        compiler.path = Some(path.clone());
+        compiler.namespace = Some(prefix.clone());
+        compiler.globals = self.globals.clone();
+        compiler.params = self.params.clone();
+        compiler.modules = self.modules.clone();
+        let function = compiler.compile();
+
+        self.parser.diagnostics.append(&mut compiler.parser.diagnostics);
+        let function = match function {
+            Some(function) => function,
+            None => {
+                self.parser.error_at_token(path_token, format!("Module '{}' failed to compile", import), self.source);
+                return None;
+            }
+        };
+        self.globals = compiler.globals.clone();
+
+        let member_prefix = format!("{}.", prefix);
+        let exports = compiler
+            .global_types
+            .iter()
+            .filter_map(|(name, member_type)| name.strip_prefix(member_prefix.as_str()).map(|member| (member.to_string(), member_type.clone())))
+            .collect();
+        let module = Module { prefix, exports, constants: compiler.top_level_constants() };
+        self.modules.borrow_mut().add_module(path, module.clone());
+
+        let offset = self.get_chunk_mut().add_constant(Value::ValFunction(function));
+        self.emit_byte(OpConstant(offset));
+        self.emit_bytes(&[OpCode::OpCall(0), OpCode::OpPop]);
+        Some(module)
+    }
+
     fn function(&mut self, function_type: FunctionType) {
         let mut compiler = Compiler::new_from_existing(self.parser.clone(), self.scanner.clone(), self.source, function_type);
+        compiler.in_test = self.in_test;
+        self.share_globals(&mut compiler);
 
         compiler.begin_scope();
         compiler.parser.consume(TokenType::LeftParen, String::from("Expect '(' after function name"), self.scanner.clone());
+        let mut params = vec![];
         if !compiler.check_token(TokenType::RightParen) {
             loop {
                 compiler.function.arity += 1;
@@ -359,6 +857,13 @@ impl<'a> Compiler<'a> {
                 }
 
                 let constant = compiler.parse_variable("Expect parameter name");
+                let annotated = compiler.match_token(TokenType::Colon);
+                let param_type = if annotated { compiler.parse_type() } else { Type::Any };
+                let param = compiler.locals.last_mut().expect("Locals array is empty!");
+                param.ty = param_type.clone();
+                param.annotated = annotated;
+                param.kind = DeclarationKind::Parameter;
+                params.push(param_type);
                 compiler.define_variable(constant);
 
                 if !compiler.match_token(TokenType::Comma) {
@@ -367,27 +872,98 @@ impl<'a> Compiler<'a> {
             }
         }
         compiler.parser.consume(TokenType::RightParen, String::from("Expect ')' after parameters"), self.scanner.clone());
+        compiler.return_type = if compiler.match_token(TokenType::Arrow) { compiler.parse_type() } else { Type::Any };
+
+        let signature = Arc::new(Signature {
+            name: compiler.function.name.clone(),
+            params,
+            returns: compiler.return_type.clone(),
+            optional: 0,
+        });
+        //lets the body call the function recursively with checked arguments
+        if self.function_type == FunctionType::Script && self.scope_depth == 0 {
+            compiler.global_types.insert(self.global_name(signature.name.as_str()), Type::Function(Some(signature.clone())));
+        }
+
         compiler.parser.consume(TokenType::LeftBrace, String::from("Expect '{' before function body"), self.scanner.clone());
         compiler.block();
+        compiler.report_unused_locals(1);
 
         let function = compiler.end_compiler();
+        //recursive calls don't make a function used
+        for name in std::mem::take(&mut compiler.global_reads) {
+            if name != function.name {
+                self.record_global_read(name.as_str());
+            }
+        }
         let offset = self.get_chunk_mut().add_constant(Value::ValFunction(function));
         self.emit_byte(OpConstant(offset));
         self.parser = compiler.parser;
         self.scanner = compiler.scanner;
+        self.push_type(Type::Function(Some(signature)));
+    }
+
+    fn parse_type(&mut self) -> Type {
+        self.parser.consume(TokenType::Identifier, String::from("Expect type name"), self.scanner.clone());
+        let token = self.previous_token();
+        let name = token.get_token_string(self.source);
+        match Type::from_name(name.as_str()) {
+            Some(parsed) => parsed,
+            None => {
+                self.type_error(&token, format!("Unknown type '{}'", name));
+                Type::Any
+            }
+        }
     }
 
     fn call(&mut self, _can_assign: bool) {
-        let arg_count = self.argument_list();
+        let paren = self.previous_token();
+        let params = match self.types.last() {
+            Some(Type::Function(Some(signature))) => Some((signature.params.len(), signature.optional, signature.name == "inject_sequence")),
+            _ => None,
+        };
+        let (arguments, ranges) = self.argument_list();
+        //optional arguments that are left out are passed as nil, so the callee always gets all of them
+        let mut arg_count = arguments.len();
+        if let Some((params, optional, typed)) = params {
+            if arg_count < params && arg_count + optional >= params {
+                for _ in arg_count..params {
+                    self.emit_left_out_argument(typed);
+                }
+                arg_count = params;
+            }
+        }
         self.emit_byte(OpCode::OpCall(arg_count));
+
+        let callee = self.pop_type();
+        let result = self.check_call(&callee, &paren, &arguments);
+        if let Type::Function(Some(signature)) = &callee {
+            if native_by_name(signature.name.as_str()).map_or(false, |native| native.signature() == **signature) {
+                self.check_native_arguments(signature.name.as_str(), &arguments, &ranges);
+            }
+        }
+        self.push_type(result);
+    }
+
+    //typing delays come from a native call, so SanVM builds that can't type with the profile report the line
+    fn emit_left_out_argument(&mut self, typed: bool) {
+        if !typed {
+            self.emit_byte(OpCode::OpNil);
+            return;
+        }
+        let native = self.identifier_constant_string(String::from("__typing_default"));
+        self.emit_bytes(&[OpCode::OpGetGlobal(native), OpCode::OpCall(0)]);
     }
 
-    fn argument_list(&mut self) -> usize {
-        let mut arg_count = 0;
+    //the arguments and the code each of them compiled to
+    fn argument_list(&mut self) -> (Vec<(Type, Token)>, Vec<Range<usize>>) {
+        let mut arguments = vec![];
+        let mut ranges = vec![];
         if !self.check_token(TokenType::RightParen) {
             loop {
-                self.expression();
-                arg_count += 1;
+                let start = self.get_chunk().len();
+                arguments.push(self.typed_expression());
+                ranges.push(start..self.get_chunk().len());
                 if !self.match_token(TokenType::Comma) {
                     break;
                 }
@@ -395,43 +971,228 @@ impl<'a> Compiler<'a> {
         }
 
         self.parser.consume(TokenType::RightParen, String::from("Expect ')' after arguments"), self.scanner.clone());
-        arg_count
+        (arguments, ranges)
+    }
+
+    //values out of range are reported at compile time when the arguments fold to constants
+    fn check_native_arguments(&mut self, name: &str, arguments: &[(Type, Token)], ranges: &[Range<usize>]) {
+        let instructions = decode(self.get_chunk());
+        let constants: Vec<Option<Value>> = ranges
+            .iter()
+            .map(|range| {
+                let argument = instructions.iter().filter(|instruction| range.contains(&instruction.id)).cloned().collect();
+                match fold_constants(argument).as_slice() {
+                    [instruction] => constant_value(instruction)?.to_constant(),
+                    _ => None
+                }
+            })
+            .collect();
+        if let Some((index, message)) = check_constant_arguments(name, &constants) {
+            self.type_error(&arguments[index].1, message);
+        }
+    }
+
+    //checks the arguments against the signature of the callee and returns the type of the call
+    fn check_call(&mut self, callee: &Type, paren: &Token, arguments: &[(Type, Token)]) -> Type {
+        let signature = match callee {
+            Type::Function(Some(signature)) => signature.clone(),
+            Type::Function(None) | Type::Any => return Type::Any,
+            other => {
+                self.type_error(paren, format!("Can't call a value of type {}", other));
+                return Type::Any;
+            }
+        };
+
+        let required = signature.params.len() - signature.optional;
+        if arguments.len() > signature.params.len() || arguments.len() < required {
+            let expected = if signature.optional > 0 {
+                format!("{} to {}", required, signature.params.len())
+            } else {
+                signature.params.len().to_string()
+            };
+            self.type_error(paren, format!("'{}' expects {} arguments but got {}", signature.name, expected, arguments.len()));
+        }
+
+        for (index, (expected, (actual, span))) in signature.params.iter().zip(arguments).enumerate() {
+            if !expected.accepts(actual) {
+                self.type_error(span, format!("Argument {} of '{}' expects {}, found {}", index + 1, signature.name, expected, actual));
+            }
+        }
+
+        signature.returns.clone()
     }
 
     fn variable_declaration(&mut self) {
         let var_name = self.parse_variable("Expect variable name");
-
-        if self.match_token(TokenType::Equal) {
-            self.expression();
+        let name = self.previous_token();
+        self.declare_kind(&name, DeclarationKind::Variable);
+        let annotation = if self.match_token(TokenType::Colon) { Some(self.parse_type()) } else { None };
+
+        let initializer = if self.match_token(TokenType::Equal) {
+            let (initializer, span) = self.typed_expression();
+            if let Some(expected) = &annotation {
+                if !expected.accepts(&initializer) {
+                    let message = format!("'{}' is declared as {} but initialized with {}", name.get_token_string(self.source), expected, initializer);
+                    self.type_error(&span, message);
+                }
+            }
+            initializer
         } else {
             self.emit_byte(OpCode::OpNil);
-        }
+            Type::Nil
+        };
 
         self.parser.consume(
             TokenType::Semicolon,
             String::from("Expect ';' after value"),
             self.scanner.clone(),
         );
+
+        //a variable that starts out as nil is expected to get a value of any type later
+        let annotated = annotation.is_some();
+        let var_type = match annotation {
+            Some(annotation) => annotation,
+            None if initializer == Type::Nil => Type::Any,
+            None => initializer,
+        };
+        self.set_variable_type(&name, var_type, annotated);
         self.define_variable(var_name);
     }
 
+    fn const_declaration(&mut self) {
+        self.parser.consume(TokenType::Identifier, String::from("Expect constant name"), self.scanner.clone());
+        let name = self.previous_token();
+        let name_string = name.get_token_string(self.source);
+        let redeclared = if self.check_constant_name(&name) {
+            true
+        } else if self.scope_depth > 0 {
+            let redeclared = self.locals.iter().any(|local| local.depth == self.scope_depth && self.identifiers_equal(&local.token, &name));
+            if redeclared {
+                self.parser.error_at_token(&name, String::from("Variable redeclaration in the same scope"), self.source);
+            }
+            redeclared
+        } else {
+            let redeclared = self.function_type == FunctionType::Script && match self.namespace {
+                Some(_) => self.module_globals.contains(&name_string) || native_by_name(name_string.as_str()).is_some(),
+                None => self.is_global(name_string.as_str()),
+            };
+            if redeclared {
+                self.parser.error_at_token(&name, format!("'{}' is already declared as a global", name_string), self.source);
+            }
+            redeclared
+        };
+        if self.scope_depth == 0 && !redeclared {
+            self.declare_kind(&name, DeclarationKind::Constant);
+        }
+
+        let annotation = if self.match_token(TokenType::Colon) { Some(self.parse_type()) } else { None };
+        self.parser.consume(TokenType::Equal, String::from("Expect '=' after constant name"), self.scanner.clone());
+
+        let (value, initializer, span) = self.constant_expression();
+        if let Some(expected) = &annotation {
+            if !expected.accepts(&initializer) {
+                let message = format!("'{}' is declared as {} but initialized with {}", name_string, expected, initializer);
+                self.type_error(&span, message);
+            }
+        }
+        let value = match value {
+            Some(value) => value,
+            None => {
+                let message = format!("Constant '{}' must be initialized with a value known at compile time", name_string);
+                self.parser.error_at_token(&span, message, self.source);
+                Value::ValNil
+            }
+        };
+
+        self.parser.consume(TokenType::Semicolon, String::from("Expect ';' after constant value"), self.scanner.clone());
+        self.constants.push(Constant {
+            name: name_string,
+            token: name,
+            depth: self.scope_depth,
+            value,
+            ty: annotation.unwrap_or(initializer),
+            used: false,
+        });
+    }
+
+    //the expression goes into a scratch chunk that is folded and thrown away, the value is None
+    //unless it folds down to a single constant
+    fn constant_expression(&mut self) -> (Option<Value>, Type, Token) {
+        let chunk = std::mem::replace(self.get_chunk_mut(), Chunk::new());
+        let (value_type, span) = self.typed_expression();
+        let scratch = std::mem::replace(self.get_chunk_mut(), chunk);
+        (Compiler::evaluate_constant(&scratch), value_type, span)
+    }
+
+    fn evaluate_constant(chunk: &Chunk) -> Option<Value> {
+        match fold_constants(decode(chunk)).as_slice() {
+            [instruction] => constant_value(instruction)?.to_constant(),
+            _ => None
+        }
+    }
+
+    fn evaluate_condition(chunk: &Chunk) -> Option<bool> {
+        match fold_constants(decode(chunk)).as_slice() {
+            [instruction] => constant_value(instruction).map(|value| !value.is_falsey()),
+            _ => None
+        }
+    }
+
+    //constants share the namespace of the variables declared in their scope
+    fn check_constant_name(&mut self, name: &Token) -> bool {
+        let name_string = name.get_token_string(self.source);
+        let redeclared = self.constants.iter().any(|constant| constant.depth == self.scope_depth && constant.name == name_string);
+        if redeclared {
+            self.parser.error_at_token(name, format!("'{}' is already declared as a constant", name_string), self.source);
+        }
+        redeclared
+    }
+
+    fn top_level_constants(&self) -> Vec<Constant> {
+        self.constants.iter().filter(|constant| constant.depth == 0).cloned().collect()
+    }
+
+    //what a function or test body sees of the top level it is declared in
+    fn share_globals(&self, compiler: &mut Compiler<'a>) {
+        compiler.global_types = self.global_types.clone();
+        compiler.annotated_globals = self.annotated_globals.clone();
+        compiler.globals = self.globals.clone();
+        compiler.constants = self.top_level_constants();
+        compiler.params = self.params.clone();
+        compiler.modules = self.modules.clone();
+        compiler.path = self.path.clone();
+        compiler.namespace = self.namespace.clone();
+        compiler.module_globals = self.module_globals.clone();
+        compiler.aliases = self.aliases.clone();
+    }
+
     fn parse_variable(&mut self, error_msg: &str) -> usize {
         self.parser.consume(
             TokenType::Identifier,
             String::from(error_msg),
             self.scanner.clone(),
         );
+        let name = self.previous_token();
+        self.check_constant_name(&name);
         self.declare_variable();
         if self.scope_depth > 0 {
             return 0;
         }
 
-        let identifier = self
-            .parser
-            .previous
-            .as_ref()
-            .expect("Parser does not have processed token!");
-        return self.identifier_constant(identifier.clone());
+        let name = name.get_token_string(self.source);
+        if self.namespace.is_some() {
+            self.module_globals.insert(name.clone());
+        }
+        let global = self.global_name(name.as_str());
+        return self.identifier_constant_string(global);
+    }
+
+    //top level names of an imported file live under the prefix of its module
+    fn global_name(&self, name: &str) -> String {
+        match &self.namespace {
+            Some(prefix) if self.module_globals.contains(name) => format!("{}.{}", prefix, name),
+            _ => name.to_string(),
+        }
     }
 
     fn declare_variable(&mut self) {
@@ -459,6 +1220,7 @@ impl<'a> Compiler<'a> {
             }
         }
 
+        self.check_shadowing(&variable);
         self.add_local(variable);
     }
 
@@ -468,11 +1230,51 @@ impl<'a> Compiler<'a> {
             return;
         }
 
+        if self.function_type == FunctionType::Script {
+            if let Value::ValString(name) = self.get_chunk().get_constant(global).clone() {
+                self.define_global(name.as_str());
+            }
+        }
         self.emit_byte(OpCode::OpDefineGlobal(global));
     }
 
-    fn identifier_constant(&mut self, identifier: Token) -> usize {
-        let token_string = identifier.get_token_string(self.source);
+    fn define_global(&mut self, name: &str) {
+        if !self.globals.iter().any(|global| global == name) {
+            self.globals.push(name.to_string());
+        }
+    }
+
+    //natives count as globals that are always declared
+    fn is_global(&self, name: &str) -> bool {
+        self.globals.iter().any(|global| global == name) || native_by_name(name).is_some()
+    }
+
+    fn set_variable_type(&mut self, name: &Token, var_type: Type, annotated: bool) {
+        if self.scope_depth > 0 {
+            let local = self.locals.last_mut().expect("Locals array is empty!");
+            local.ty = var_type;
+            local.annotated = annotated;
+        } else {
+            let global = self.global_name(name.get_token_string(self.source).as_str());
+            self.global_types.insert(global.clone(), var_type);
+            if annotated {
+                self.annotated_globals.insert(global);
+            } else {
+                self.annotated_globals.remove(&global);
+            }
+        }
+    }
+
+    //globals without a known declaration, like functions that are declared further down, are Any
+    fn global_type(&self, name: &str) -> Type {
+        self.global_types
+            .get(name)
+            .cloned()
+            .or_else(|| native_by_name(name).map(|native| native.function_type()))
+            .unwrap_or(Type::Any)
+    }
+
+    fn identifier_constant_string(&mut self, token_string: String) -> usize {
         let chunk = self.get_chunk_mut();
         let ident_value = Value::ValString(token_string);
         let offset = chunk.has_constant(&ident_value);
@@ -487,7 +1289,7 @@ impl<'a> Compiler<'a> {
     }
 
     fn add_local(&mut self, token: Token) {
-        let local: Local = Local { token, depth: -1 };
+        let local: Local = Local { token, depth: -1, ty: Type::Any, annotated: false, kind: DeclarationKind::Variable, used: false };
 
         self.locals.push(local);
     }
@@ -502,6 +1304,7 @@ impl<'a> Compiler<'a> {
     }
 
     fn statement(&mut self) {
+        self.mark_statement();
         if self.match_token(TokenType::Print) {
             self.print_statement();
         } else if self.match_token(TokenType::LeftBrace) {
@@ -516,6 +1319,12 @@ impl<'a> Compiler<'a> {
             self.for_statement();
         } else if self.match_token(TokenType::Return) {
             self.return_statement();
+        } else if self.match_token(TokenType::Assert) {
+            self.assert_statement();
+        } else if self.match_token(TokenType::Typing) {
+            self.typing_statement();
+        } else if self.match_token(TokenType::Hold) {
+            self.hold_statement();
         } else {
             self.expression_statement();
         }
@@ -527,12 +1336,24 @@ impl<'a> Compiler<'a> {
             String::from("Expect '(' after 'if'"),
             self.scanner.clone(),
         );
+        let target_reads = self.target_reads;
+        let chunk = std::mem::replace(self.get_chunk_mut(), Chunk::new());
         self.expression();
+        let condition = std::mem::replace(self.get_chunk_mut(), chunk);
         self.parser.consume(
             TokenType::RightParen,
             String::from("Expect ')' after condition"),
             self.scanner.clone(),
         );
+        self.pop_type();
+
+        if self.target_reads > target_reads {
+            if let Some(taken) = Compiler::evaluate_condition(&condition) {
+                self.target_branches(taken);
+                return;
+            }
+        }
+        append(self.get_chunk_mut(), &decode(&condition));
 
         let then_jump = self.emit_jump(OpCode::OpJumpIfFalse(0xff));
         self.emit_byte(OpCode::OpPop);
@@ -547,6 +1368,22 @@ impl<'a> Compiler<'a> {
         self.patch_jump(else_jump);
     }
 
+    //only the branch for the target is compiled, so the other one leaves nothing in the binary
+    fn target_branches(&mut self, taken: bool) {
+        if taken {
+            self.statement();
+        } else {
+            self.skip_declaration();
+        }
+        if self.match_token(TokenType::Else) {
+            if taken {
+                self.skip_declaration();
+            } else {
+                self.statement();
+            }
+        }
+    }
+
     fn while_statement(&mut self) {
         let loop_start = self.get_chunk().len();
         self.parser.consume(
@@ -560,6 +1397,7 @@ impl<'a> Compiler<'a> {
             String::from("Expect ')' after condition"),
             self.scanner.clone(),
         );
+        self.pop_type();
 
         let exit_jump = self.emit_jump(OpCode::OpJumpIfFalse(0xff));
         self.emit_byte(OpCode::OpPop);
@@ -591,6 +1429,7 @@ impl<'a> Compiler<'a> {
                 String::from("Expect ';' after loop condition."),
                 self.scanner.clone(),
             );
+            self.pop_type();
             exit_jump = self.emit_jump(OpCode::OpJumpIfFalse(0xff));
             self.emit_byte(OpCode::OpPop);
         }
@@ -599,6 +1438,7 @@ impl<'a> Compiler<'a> {
             let body_jump = self.emit_jump(OpCode::OpJump(0xff));
             let increment_start = self.get_chunk().len();
             self.expression();
+            self.pop_type();
             self.emit_byte(OpCode::OpPop);
             self.parser.consume(
                 TokenType::RightParen,
@@ -625,16 +1465,148 @@ impl<'a> Compiler<'a> {
         if self.function_type == FunctionType::Script {
             self.parser.error(String::from("Can't return from top-level code"), self.source);
         }
+        let keyword = self.previous_token();
 
         if self.match_token(TokenType::Semicolon) {
+            if !self.return_type.accepts(&Type::Nil) {
+                let message = format!("'{}' must return a value of type {}", self.function.name, self.return_type);
+                self.type_error(&keyword, message);
+            }
+            self.release_holds(0);
             self.emit_return();
         } else {
-            self.expression();
+            let (value, span) = self.typed_expression();
+            if !self.return_type.accepts(&value) {
+                let message = format!("'{}' returns {}, found {}", self.function.name, self.return_type, value);
+                self.type_error(&span, message);
+            }
             self.parser.consume(TokenType::Semicolon, String::from("Expect ';' after return value"), self.scanner.clone());
+            //the value is already on the stack, so keys typed while computing it are still held
+            self.release_holds(0);
             self.emit_byte(OpCode::OpReturn);
+            self.returns_value = true;
+        }
+    }
+
+    fn assert_statement(&mut self) {
+        if !self.in_test {
+            self.parser.error(String::from("Can't use 'assert' outside of a test block"), self.source);
+        }
+
+        self.expression();
+        self.pop_type();
+        let message = if self.match_token(TokenType::Comma) {
+            self.parser.consume(TokenType::String, String::from("Expect assertion message"), self.scanner.clone());
+            let message = self
+                .parser
+                .previous
+                .as_ref()
+                .expect("Parser does not have processed token!")
+                .get_token_string(self.source);
+            message.trim_matches('"').to_string()
+        } else {
+            String::from("assertion failed")
+        };
+        self.parser.consume(TokenType::Semicolon, String::from("Expect ';' after assertion"), self.scanner.clone());
+
+        //the simulator provides __assert_fail, which stops the test with the given message
+        let pass_jump = self.emit_jump(OpCode::OpJumpIfTrue(0xff));
+        self.emit_byte(OpCode::OpPop);
+        let fail_native = self.identifier_constant_string(String::from("__assert_fail"));
+        self.emit_byte(OpCode::OpGetGlobal(fail_native));
+        self.emit_constant(Value::ValString(message));
+        self.emit_bytes(&[OpCode::OpCall(1), OpCode::OpPop]);
+        let end_jump = self.emit_jump(OpCode::OpJump(0xff));
+        self.patch_jump(pass_jump);
+        self.emit_byte(OpCode::OpPop);
+        self.patch_jump(end_jump);
+    }
+
+    //`hold SHIFT { ... }` presses the keys with key_down and releases them with key_up when the block is left,
+    //the keys are evaluated once into a local named after the keyword, which no identifier can refer to
+    fn hold_statement(&mut self) {
+        let keyword = self.previous_token();
+        self.begin_scope();
+        let (keys, span) = self.typed_expression();
+        if !Type::Keys.accepts(&keys) {
+            self.type_error(&span, format!("'hold' expects Keys, found {}", keys));
+        }
+        let slot = self.locals.len();
+        self.locals.push(Local { token: keyword, depth: self.scope_depth, ty: Type::Keys, annotated: false, kind: DeclarationKind::Variable, used: true });
+        self.emit_key_native("key_down", slot);
+
+        self.parser.consume(TokenType::LeftBrace, String::from("Expect '{' after held keys"), self.scanner.clone());
+        self.holds.push((slot, self.scope_depth));
+        self.begin_scope();
+        self.block();
+        self.end_scope();
+        self.holds.pop();
+        self.emit_key_native("key_up", slot);
+        self.end_scope();
+    }
+
+    //releases the keys of the holds inside scopes deeper than depth, innermost first, for statements that
+    //leave those scopes without reaching their end
+    fn release_holds(&mut self, depth: isize) {
+        let holds: Vec<usize> = self.holds.iter().rev().filter(|(_, hold_depth)| *hold_depth > depth).map(|(slot, _)| *slot).collect();
+        for slot in holds {
+            self.emit_key_native("key_up", slot);
+        }
+    }
+
+    fn emit_key_native(&mut self, name: &str, slot: usize) {
+        let native = self.identifier_constant_string(name.to_string());
+        self.emit_byte(OpCode::OpGetGlobal(native));
+        self.emit_byte(OpCode::OpGetLocal(slot));
+        self.emit_bytes(&[OpCode::OpCall(1), OpCode::OpPop]);
+    }
+
+    //`typing { speed: 30ms, jitter: 10ms, seed: 42 }`, settings that are left out keep their default
+    fn typing_statement(&mut self) {
+        self.parser.consume(TokenType::LeftBrace, String::from("Expect '{' after 'typing'"), self.scanner.clone());
+        let mut profile = TypingProfile::default();
+        while !self.check_token(TokenType::RightBrace) && !self.check_token(TokenType::EOF) {
+            self.parser.consume(TokenType::Identifier, String::from("Expect typing setting name"), self.scanner.clone());
+            let setting = self.previous_token();
+            self.parser.consume(TokenType::Colon, String::from("Expect ':' after typing setting name"), self.scanner.clone());
+            let value = self.typing_value();
+            if let Err(message) = profile.set(setting.get_token_string(self.source).as_str(), value) {
+                self.parser.error_at_token(&setting, message, self.source);
+            }
+            if !self.match_token(TokenType::Comma) {
+                break;
+            }
+        }
+        self.parser.consume(TokenType::RightBrace, String::from("Expect '}' after typing settings"), self.scanner.clone());
+        self.emit_typing_profile(profile);
+    }
+
+    //a duration known at compile time, optionally followed by `ms`
+    fn typing_value(&mut self) -> u64 {
+        let (value, _, span) = self.constant_expression();
+        let unit = self.parser.current.as_ref().map(|token| token.get_token_string(self.source));
+        if self.check_token(TokenType::Identifier) && unit.as_deref() == Some("ms") {
+            self.parser.advance(self.scanner.clone());
+        }
+        match value {
+            Some(Value::ValNumber(number)) if number >= 0.0 => number as u64,
+            _ => {
+                self.parser.error_at_token(&span, String::from("Typing settings must be non-negative numbers known at compile time"), self.source);
+                0
+            }
         }
     }
 
+    //the profile is only known to the compiler, SanVM gets it through a native and draws the jitter on the device
+    fn emit_typing_profile(&mut self, profile: TypingProfile) {
+        let native = self.identifier_constant_string(String::from("__set_typing"));
+        self.emit_byte(OpCode::OpGetGlobal(native));
+        for value in [profile.speed, profile.jitter, profile.seed] {
+            self.emit_constant(Value::ValNumber(value as Number));
+        }
+        self.emit_bytes(&[OpCode::OpCall(3), OpCode::OpPop]);
+    }
+
     fn emit_jump(&mut self, instruction: OpCode) -> usize {
         self.emit_byte(instruction);
         self.get_chunk().len() - 1
@@ -666,6 +1638,7 @@ impl<'a> Compiler<'a> {
     }
 
     fn end_scope(&mut self) {
+        self.report_unused_locals(self.scope_depth);
         self.scope_depth -= 1;
 
         for i in (0..self.locals.len()).rev() {
@@ -680,6 +1653,8 @@ impl<'a> Compiler<'a> {
                 self.emit_byte(OpCode::OpPop);
             }
         }
+        let scope_depth = self.scope_depth;
+        self.constants.retain(|constant| constant.depth <= scope_depth);
     }
 
     fn block(&mut self) {
@@ -714,6 +1689,7 @@ impl<'a> Compiler<'a> {
 
     fn print_statement(&mut self) {
         self.expression();
+        self.pop_type();
         self.parser.consume(
             TokenType::Semicolon,
             String::from("Expect ';' after value"),
@@ -724,6 +1700,11 @@ impl<'a> Compiler<'a> {
 
     fn expression_statement(&mut self) {
         self.expression();
+        self.pop_type();
+        if self.repl && self.function_type == FunctionType::Script && self.scope_depth == 0 && self.check_token(TokenType::EOF) {
+            self.emit_byte(OpCode::OpReturn);
+            return;
+        }
         self.parser.consume(
             TokenType::Semicolon,
             String::from("Expect ';' after value"),
@@ -735,6 +1716,35 @@ impl<'a> Compiler<'a> {
         self.parse_precedence(Precedence::Assignment);
     }
 
+    //compiles an expression and returns its type along with a token spanning the whole expression
+    fn typed_expression(&mut self) -> (Type, Token) {
+        let start = self.parser.current.clone().expect("Parser does not have current token processed!");
+        self.expression();
+        let end = self.previous_token();
+        let length = (end.start_index + end.length).saturating_sub(start.start_index);
+        (self.pop_type(), Token { length, ..start })
+    }
+
+    fn push_type(&mut self, value_type: Type) {
+        self.types.push(value_type);
+    }
+
+    //an expression that failed to parse leaves no type behind, so a missing type is Any
+    fn pop_type(&mut self) -> Type {
+        self.types.pop().unwrap_or(Type::Any)
+    }
+
+    fn type_error(&mut self, token: &Token, message: String) {
+        self.parser.error_at_token(token, message, self.source);
+    }
+
+    fn previous_token(&self) -> Token {
+        self.parser
+            .previous
+            .clone()
+            .expect("Parser does not have processed token!")
+    }
+
     fn parse_precedence(&mut self, precedence: Precedence) {
         self.parser.advance(self.scanner.clone());
         let previous_token: usize = self
@@ -809,10 +1819,47 @@ impl<'a> Compiler<'a> {
     }
 
     fn end_compiler(&mut self) -> FunctionData {
+        let implicit_return = self.get_chunk().len();
         self.emit_return();
+        self.report_unreachable(implicit_return);
         self.function.clone()
     }
 
+    //statements inside a declaration are marked twice, the first mark is enough
+    fn mark_statement(&mut self) {
+        let token = self.parser.current.clone().expect("Parser does not have processed token!");
+        if let Some((_, last)) = self.statements.last() {
+            if last.start_index == token.start_index {
+                return;
+            }
+        }
+        self.statements.push((self.get_chunk().len(), token));
+    }
+
+    //only the first statement of an unreachable stretch is reported
+    fn report_unreachable(&mut self, implicit_return: usize) {
+        if self.parser.had_error {
+            return;
+        }
+
+        let reachable = reachable(&decode(self.get_chunk()));
+        let mut previous_reachable = true;
+        let statements = std::mem::take(&mut self.statements);
+        for (offset, token) in statements {
+            let is_reachable = reachable.get(offset).copied().unwrap_or(false);
+            if !is_reachable && previous_reachable {
+                self.parser.warning_at(&token, UNREACHABLE, String::from("Unreachable code"), self.source);
+            }
+            previous_reachable = is_reachable;
+        }
+
+        if self.returns_value && reachable[implicit_return] {
+            let token = self.previous_token();
+            let message = format!("Not every path of '{}' returns a value", self.function.name);
+            self.parser.warning_at(&token, MISSING_RETURN, message, self.source);
+        }
+    }
+
     fn emit_byte(&mut self, byte: OpCode) {
         let parser_line = self.parser
             .previous
@@ -842,6 +1889,15 @@ impl<'a> Compiler<'a> {
         self.emit_byte(OpCode::OpReturn);
     }
 
+    fn emit_constant_value(&mut self, value: Value) {
+        match value {
+            Value::ValNil => self.emit_byte(OpCode::OpNil),
+            Value::ValBool(true) => self.emit_byte(OpCode::OpTrue),
+            Value::ValBool(false) => self.emit_byte(OpCode::OpFalse),
+            value => self.emit_constant(value),
+        }
+    }
+
     fn emit_constant(&mut self, value: Value) {
         let chunk = self.get_chunk_mut();
         let mut offset = chunk.has_constant(&value);
@@ -862,6 +1918,7 @@ impl<'a> Compiler<'a> {
             .parse::<Number>()
             .unwrap_or_else(|_| panic!("Could not parse token value to number!"));
         self.emit_constant(Value::ValNumber(value));
+        self.push_type(Type::Number);
     }
 
     fn hid_key(&mut self, _can_assign: bool) {
@@ -880,6 +1937,7 @@ impl<'a> Compiler<'a> {
                 self.source,
             );
         }
+        self.push_type(Type::Keys);
     }
 
     fn mouse_button(&mut self, _can_assign: bool) {
@@ -898,6 +1956,17 @@ impl<'a> Compiler<'a> {
                 self.source,
             );
         }
+        self.push_type(Type::MouseButton);
+    }
+
+    //the value is the encoded number, only the static type tells it apart from other numbers
+    fn media_key(&mut self, _can_assign: bool) {
+        let name = self.previous_token().get_token_string(self.source);
+        match media_key(name.as_str()) {
+            Some(key) => self.emit_constant(Value::ValNumber(key.code() as Number)),
+            None => self.parser.error(format!("Constant {} is not a valid media key", name), self.source),
+        }
+        self.push_type(Type::MediaKey);
     }
 
     fn literal(&mut self, _can_assign: bool) {
@@ -915,6 +1984,7 @@ impl<'a> Compiler<'a> {
             TokenType::Nil => self.emit_byte(OpCode::OpNil),
             _ => return,
         }
+        self.push_type(if token_type == TokenType::Nil { Type::Nil } else { Type::Bool });
     }
 
     fn string(&mut self, _can_assign: bool) {
@@ -926,6 +1996,74 @@ impl<'a> Compiler<'a> {
             .get_token_string(self.source);
         let string_literal = &value[1..value.len() - 1].to_string();
         self.emit_constant(Value::ValString(string_literal.to_owned()));
+        self.push_type(Type::String);
+    }
+
+    //`param("name", default)` is replaced by the value passed to the compiler, a param without a default is required
+    fn param(&mut self, _can_assign: bool) {
+        let keyword = self.previous_token();
+        self.parser.consume(TokenType::LeftParen, String::from("Expect '(' after 'param'"), self.scanner.clone());
+        self.parser.consume(TokenType::String, String::from("Expect parameter name"), self.scanner.clone());
+        let name = self.previous_token().get_token_string(self.source).trim_matches('"').to_string();
+
+        let default = if self.match_token(TokenType::Comma) {
+            let (value, _, span) = self.constant_expression();
+            if value.is_none() {
+                let message = format!("Default of parameter '{}' must be known at compile time", name);
+                self.parser.error_at_token(&span, message, self.source);
+            }
+            value
+        } else {
+            None
+        };
+        self.parser.consume(TokenType::RightParen, String::from("Expect ')' after parameter"), self.scanner.clone());
+
+        let value = match (self.params.get(name.as_str()).cloned(), default) {
+            (Some(value), None) => value,
+            (Some(value), Some(default)) => self.coerce_param(&keyword, name.as_str(), value, &default),
+            (None, Some(default)) => default,
+            (None, None) => {
+                let message = format!("Missing required parameter '{}', pass it with -D {}=<value> or add it to [params] in {}", name, name, CONFIG_FILE);
+                self.parser.error_at_token(&keyword, message, self.source);
+                Value::ValNil
+            }
+        };
+        self.push_type(Type::of_value(&value));
+        self.emit_constant_value(value);
+    }
+
+    fn target(&mut self, _can_assign: bool) {
+        let keyword = self.previous_token();
+        self.target_reads += 1;
+        let target = self.target_name(&keyword).map_or(Value::ValNil, Value::ValString);
+        self.push_type(Type::String);
+        self.emit_constant_value(target);
+    }
+
+    fn target_name(&mut self, keyword: &Token) -> Option<String> {
+        let message = match self.params.get(TARGET_PARAM) {
+            Some(Value::ValString(target)) if TARGETS.contains(&target.as_str()) => return Some(target.clone()),
+            Some(Value::ValString(target)) => format!("Unknown target '{}', expected one of {}", target, TARGETS.join(", ")),
+            Some(_) => format!("Target must be one of {}", TARGETS.join(", ")),
+            None => format!("Missing target, pass it with --target <{}> or add {} to [params] in {}", TARGETS.join("|"), TARGET_PARAM, CONFIG_FILE),
+        };
+        self.parser.error_at_token(keyword, message, self.source);
+        None
+    }
+
+    //-D values are untyped, so a param with a string default takes numbers and booleans as text
+    fn coerce_param(&mut self, keyword: &Token, name: &str, value: Value, default: &Value) -> Value {
+        let expected = Type::of_value(default);
+        match (value, default) {
+            (Value::ValNumber(number), Value::ValString(_)) => Value::ValString(number.to_string()),
+            (Value::ValBool(boolean), Value::ValString(_)) => Value::ValString(boolean.to_string()),
+            (value, _) if expected.accepts(&Type::of_value(&value)) => value,
+            (value, _) => {
+                let message = format!("Parameter '{}' must be {} like its default, found {}", name, expected, Type::of_value(&value));
+                self.parser.error_at_token(keyword, message, self.source);
+                value
+            }
+        }
     }
 
     fn grouping(&mut self, _can_assign: bool) {
@@ -938,31 +2076,31 @@ impl<'a> Compiler<'a> {
     }
 
     fn unary(&mut self, _can_assign: bool) {
-        let operator_type = self
-            .parser
-            .previous
-            .as_ref()
-            .expect("Parser does not have processed token!")
-            .token_type
-            .clone();
+        let operator = self.previous_token();
+        let operator_type = operator.token_type.clone();
 
         self.parse_precedence(Precedence::Unary);
+        let operand = self.pop_type();
 
         match operator_type {
-            TokenType::Minus => self.emit_byte(OpCode::OpNegate),
-            TokenType::Bang => self.emit_byte(OpCode::OpNot),
+            TokenType::Minus => {
+                if !Type::Number.accepts(&operand) {
+                    self.type_error(&operator, format!("Operand of '-' must be a number, found {}", operand));
+                }
+                self.emit_byte(OpCode::OpNegate);
+                self.push_type(Type::Number);
+            }
+            TokenType::Bang => {
+                self.emit_byte(OpCode::OpNot);
+                self.push_type(Type::Bool);
+            }
             _ => return,
         }
     }
 
     fn binary(&mut self, _can_assign: bool) {
-        let operator_type = self
-            .parser
-            .previous
-            .as_ref()
-            .expect("No token has been processed!")
-            .token_type
-            .clone();
+        let operator = self.previous_token();
+        let operator_type = operator.token_type.clone();
         let token_index: usize = operator_type.clone().into();
         let rule = self
             .rules
@@ -972,6 +2110,17 @@ impl<'a> Compiler<'a> {
             num::FromPrimitive::from_usize((rule.precedence as usize) + 1);
         self.parse_precedence(next_precedence.unwrap());
 
+        let right = self.pop_type();
+        let left = self.pop_type();
+        let result = match binary_type(&operator_type, &left, &right) {
+            Ok(result) => result,
+            Err(message) => {
+                self.type_error(&operator, message);
+                Type::Any
+            }
+        };
+        self.push_type(result);
+
         match operator_type {
             TokenType::Plus => self.emit_byte(OpCode::OpAdd),
             TokenType::Minus => self.emit_byte(OpCode::OpSubtract),
@@ -993,6 +2142,7 @@ impl<'a> Compiler<'a> {
 
         self.emit_byte(OpCode::OpPop);
         self.parse_precedence(Precedence::And);
+        self.join_types();
 
         self.patch_jump(end_jump);
     }
@@ -1002,40 +2152,256 @@ impl<'a> Compiler<'a> {
 
         self.emit_byte(OpCode::OpPop);
         self.parse_precedence(Precedence::Or);
+        self.join_types();
 
         self.patch_jump(end_jump);
     }
 
+    //`and` and `or` evaluate to one of their operands
+    fn join_types(&mut self) {
+        let right = self.pop_type();
+        let left = self.pop_type();
+        self.push_type(left.join(&right));
+    }
+
     fn variable(&mut self, can_assign: bool) {
-        self.named_variable(
-            self.parser
-                .previous
-                .as_ref()
-                .expect("No token has been processed!")
-                .clone(),
-            can_assign,
-        );
+        let identifier = self.previous_token();
+        let name = identifier.get_token_string(self.source);
+        let is_local = self.locals.iter().any(|local| self.identifiers_equal(&local.token, &identifier));
+        if !is_local && self.check_token(TokenType::Dot) {
+            if let Some(module) = self.aliases.get(&name).cloned() {
+                self.module_member(name, module, can_assign);
+                return;
+            }
+        }
+
+        self.named_variable(identifier, can_assign);
+    }
+
+    //`alias.member` reads a global or constant of an imported module
+    fn module_member(&mut self, alias: String, module: Module, can_assign: bool) {
+        self.parser.advance(self.scanner.clone());
+        self.parser.consume(TokenType::Identifier, String::from("Expect member name after '.'"), self.scanner.clone());
+        let member_token = self.previous_token();
+        let member = member_token.get_token_string(self.source);
+
+        if can_assign && self.match_token(TokenType::Equal) {
+            let message = format!("Can't assign to '{}.{}' outside of its module", alias, member);
+            self.parser.error_at_token(&member_token, message, self.source);
+            let (value, _) = self.typed_expression();
+            self.push_type(value);
+            return;
+        }
+
+        if let Some(constant) = module.constants.iter().find(|constant| constant.name == member) {
+            self.emit_constant_value(constant.value.clone());
+            self.push_type(constant.ty.clone());
+            return;
+        }
+
+        match module.exports.get(&member) {
+            Some(member_type) => {
+                let global = self.identifier_constant_string(format!("{}.{}", module.prefix, member));
+                self.emit_byte(OpCode::OpGetGlobal(global));
+                self.push_type(member_type.clone());
+            }
+            None => {
+                self.parser.error_at_token(&member_token, format!("Module '{}' has no member '{}'", alias, member), self.source);
+                self.push_type(Type::Any);
+            }
+        }
     }
 
     fn named_variable(&mut self, identifier: Token, can_assign: bool) {
+        if let Some(index) = self.resolve_constant(&identifier) {
+            self.constant_variable(index, identifier, can_assign);
+            return;
+        }
+
         let get_op: OpCode;
         let set_op: OpCode;
+        let variable_type: Type;
+        let annotated: bool;
+        let name = identifier.get_token_string(self.source);
         let arg = self.resolve_local(&identifier);
+        let global = self.global_name(name.as_str());
 
         if arg != -1 {
             get_op = OpCode::OpGetLocal(arg as usize);
             set_op = OpCode::OpSetLocal(arg as usize);
+            variable_type = self.locals[arg as usize].ty.clone();
+            annotated = self.locals[arg as usize].annotated;
         } else {
-            let arg = self.identifier_constant(identifier);
+            self.check_global(&identifier, global.as_str());
+            variable_type = self.global_type(global.as_str());
+            annotated = self.annotated_globals.contains(&global);
+            let arg = self.identifier_constant_string(global.clone());
             get_op = OpCode::OpGetGlobal(arg);
             set_op = OpCode::OpSetGlobal(arg);
         }
 
         if can_assign && self.match_token(TokenType::Equal) {
-            self.expression();
+            let (value, span) = self.typed_expression();
+            if !variable_type.accepts(&value) {
+                if annotated {
+                    self.type_error(&span, format!("Can't assign {} to '{}' of type {}", value, name, variable_type));
+                } else if arg != -1 {
+                    self.locals[arg as usize].ty = variable_type.join(&value);
+                } else {
+                    self.global_types.insert(global, variable_type.join(&value));
+                }
+            }
             self.emit_byte(set_op);
+            self.push_type(value);
         } else {
+            if arg != -1 {
+                self.locals[arg as usize].used = true;
+            } else {
+                self.record_global_read(name.as_str());
+            }
             self.emit_byte(get_op);
+            self.push_type(variable_type);
+        }
+    }
+
+    //a constant is only hidden by a local declared in a deeper scope
+    fn resolve_constant(&self, identifier: &Token) -> Option<usize> {
+        let name = identifier.get_token_string(self.source);
+        let index = self.constants.iter().rposition(|constant| constant.name == name)?;
+        let local_depth = self
+            .locals
+            .iter()
+            .rev()
+            .find(|local| self.identifiers_equal(&local.token, identifier))
+            .map_or(-1, |local| local.depth);
+
+        if self.constants[index].depth >= local_depth { Some(index) } else { None }
+    }
+
+    fn constant_variable(&mut self, index: usize, identifier: Token, can_assign: bool) {
+        if can_assign && self.match_token(TokenType::Equal) {
+            let message = format!("Can't assign to constant '{}'", self.constants[index].name);
+            self.parser.error_at_token(&identifier, message, self.source);
+            let (value, _) = self.typed_expression();
+            self.push_type(value);
+            return;
+        }
+
+        self.constants[index].used = true;
+        let constant = self.constants[index].clone();
+        if constant.depth == 0 {
+            self.record_global_read(constant.name.as_str());
+        }
+        self.emit_constant_value(constant.value);
+        self.push_type(constant.ty);
+    }
+
+    //globals have to be declared before they are used, only functions can be referenced ahead of their declaration
+    fn check_global(&mut self, identifier: &Token, name: &str) {
+        //an imported file only sees its own globals and the natives
+        let visible = self.namespace.is_none() || name.contains('.') || native_by_name(name).is_some();
+        if visible && self.is_global(name) {
+            return;
+        }
+
+        //a name close to a native is most likely a typo
+        let message = match suggest_native(name) {
+            Some(native) => format!("Unknown function '{}', did you mean '{}'?", name, native),
+            None => format!("Undefined variable '{}'", name),
+        };
+        self.parser.error_at_token(identifier, message, self.source);
+    }
+
+    //records the kind of a new declaration, top level ones are tracked for the unused and redeclaration lints
+    fn declare_kind(&mut self, name: &Token, kind: DeclarationKind) {
+        //the name failed to parse, so the token is not a declaration
+        if self.parser.panic_mode {
+            return;
+        }
+        if self.scope_depth > 0 {
+            self.locals.last_mut().expect("Locals array is empty!").kind = kind;
+            return;
+        }
+        if self.function_type != FunctionType::Script {
+            return;
+        }
+
+        let name_string = name.get_token_string(self.source);
+        if let Some(previous) = self.global_declarations.iter().rev().find(|declaration| declaration.name == name_string) {
+            let message = format!("'{}' is already declared on line {}", name_string, previous.token.line);
+            self.parser.warning_at(name, REDECLARATION, message, self.source);
+        }
+        self.global_declarations.push(GlobalDeclaration { name: name_string, token: name.clone(), kind, used: false });
+    }
+
+    fn check_shadowing(&mut self, variable: &Token) {
+        let name = variable.get_token_string(self.source);
+        let outer_local = self
+            .locals
+            .iter()
+            .any(|local| local.depth != -1 && local.depth < self.scope_depth && self.identifiers_equal(&local.token, variable));
+
+        let message = if outer_local {
+            format!("'{}' shadows a variable of an outer scope", name)
+        } else if native_by_name(name.as_str()).is_some() {
+            format!("'{}' shadows a native function", name)
+        } else if self.is_global(self.global_name(name.as_str()).as_str()) {
+            format!("'{}' shadows a global", name)
+        } else {
+            return;
+        };
+        self.parser.warning_at(variable, SHADOWING, message, self.source);
+    }
+
+    //reads of globals inside functions are resolved by the script compiler, which owns the declarations
+    fn record_global_read(&mut self, name: &str) {
+        if self.function_type != FunctionType::Script {
+            self.global_reads.push(name.to_string());
+            return;
+        }
+
+        match self.global_declarations.iter_mut().rev().find(|declaration| declaration.name == name) {
+            Some(declaration) => declaration.used = true,
+            None => {
+                self.forward_reads.insert(name.to_string());
+            }
+        }
+    }
+
+    //names starting with an underscore are meant to be unused
+    fn report_unused_locals(&mut self, depth: isize) {
+        let mut unused: Vec<(Token, DeclarationKind)> = self
+            .locals
+            .iter()
+            .filter(|local| local.depth >= depth && !local.used)
+            .map(|local| (local.token.clone(), local.kind))
+            .collect();
+        unused.extend(self
+            .constants
+            .iter()
+            .filter(|constant| constant.depth >= depth.max(1) && !constant.used)
+            .map(|constant| (constant.token.clone(), DeclarationKind::Constant)));
+
+        for (token, kind) in unused {
+            let name = token.get_token_string(self.source);
+            if !name.is_empty() && !name.starts_with('_') {
+                self.parser.warning_at(&token, UNUSED, format!("Unused {} '{}'", kind, name), self.source);
+            }
+        }
+    }
+
+    fn report_unused_globals(&mut self) {
+        let unused: Vec<(Token, DeclarationKind)> = self
+            .global_declarations
+            .iter()
+            .filter(|declaration| !declaration.used && !self.forward_reads.contains(&declaration.name))
+            .filter(|declaration| !declaration.name.starts_with('_'))
+            .map(|declaration| (declaration.token.clone(), declaration.kind))
+            .collect();
+
+        for (token, kind) in unused {
+            let message = format!("Unused {} '{}'", kind, token.get_token_string(self.source));
+            self.parser.warning_at(&token, UNUSED, message, self.source);
         }
     }
 
@@ -1056,3 +2422,59 @@ impl<'a> Compiler<'a> {
         return -1;
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use san_common::value::Value;
+    use crate::natives::missing_on_device;
+    use crate::params::{Params, TARGET_PARAM};
+    use crate::test_support::{compile, compile_with, printed, run};
+
+    fn for_target(target: &str) -> Params {
+        let mut params = Params::new();
+        params.define(TARGET_PARAM, Value::ValString(target.to_string()));
+        params
+    }
+
+    #[test]
+    fn left_out_typing_delays_come_from_the_profile() {
+        let function = compile("inject_sequence(string_to_keys(\"ab\"));\ninject_sequence(string_to_keys(\"ab\"), 10);\ninject_sequence(string_to_keys(\"ab\"), 10, 5);\n").unwrap();
+        assert_eq!(missing_on_device(&function), vec![(1, "__typing_default"), (2, "__typing_default")]);
+        assert_eq!(run(function).duration(), 60 + 20 + 30);
+    }
+
+    #[test]
+    fn constants_are_folded_and_inlined() {
+        let function = compile("const DELAY = 10 * 5;\nconst TWICE = DELAY * 2;\nsleep(TWICE);\n").expect("source does not compile");
+        let ops: Vec<String> = (0..function.chunk.len()).map(|offset| format!("{:?}", function.chunk.get_code(offset))).collect();
+        assert!(!ops.iter().any(|op| op.starts_with("OpDefineGlobal")), "constants became globals: {:?}", ops);
+
+        assert_eq!(run(function).duration(), 100);
+    }
+
+    #[test]
+    fn constants_can_not_be_assigned() {
+        assert!(compile("const A = 1;\nA = 2;\n").is_none());
+    }
+
+    #[test]
+    fn constant_initializers_must_be_known() {
+        assert!(compile("let a = 1;\nconst B = a;\nprint B;\n").is_none());
+    }
+
+    const TARGETED: &str = "#[target(windows)]\nfn open() { print \"windows\"; }\n#[target(linux)]\nfn open() { print \"linux\"; }\nopen();\nif (target == \"windows\") { missing(); } else { print \"other\"; }\n";
+
+    #[test]
+    fn only_the_code_of_the_target_is_compiled() {
+        let function = compile_with(TARGETED, for_target("linux")).expect("source does not compile");
+        assert_eq!(printed(function), vec!["linux", "other"]);
+    }
+
+    #[test]
+    fn targets_must_be_given_and_known() {
+        assert!(compile(TARGETED).is_none());
+        assert!(compile_with(TARGETED, for_target("plan9")).is_none());
+        //the windows branch calls a function that doesn't exist
+        assert!(compile_with(TARGETED, for_target("windows")).is_none());
+    }
+}
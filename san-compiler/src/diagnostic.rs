@@ -0,0 +1,47 @@
+use std::fmt;
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+#[derive(Clone, Debug)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    pub message: String,
+    pub line: usize,
+    //source span of the offending token, None when the scanner produced the error
+    pub start_index: Option<usize>,
+    pub length: usize,
+    //file id of the token, see Token::file
+    pub file: usize,
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let severity = match self.severity {
+            Severity::Error => "Error",
+            Severity::Warning => "Warning",
+        };
+        write!(f, "[line {}] {}: {}", self.line, severity, self.message)
+    }
+}
+
+impl Diagnostic {
+    //the diagnostic followed by the offending source line with the span underlined
+    pub fn render(&self, source: &str) -> String {
+        let start = match self.start_index {
+            Some(start) => start,
+            None => return self.to_string(),
+        };
+
+        let before: Vec<char> = source.chars().take(start).collect();
+        let line_start = before.iter().rposition(|c| *c == '\n').map_or(0, |i| i + 1);
+        let line: String = source.chars().skip(line_start).take_while(|c| *c != '\n').collect();
+        let column = start - line_start;
+        let length = self.length.clamp(1, line.chars().count().saturating_sub(column).max(1));
+
+        format!("{}\n    {}\n    {}{}", self, line, " ".repeat(column), "^".repeat(length))
+    }
+}
@@ -0,0 +1,62 @@
+use std::fmt::Write;
+use san_common::chunk::{Chunk, OpCode};
+use san_common::value::{FunctionData, Value};
+use crate::simulator::SimValue;
+
+pub fn disassemble_function(function: &FunctionData) -> String {
+    let mut output = String::new();
+    let name = if function.name.is_empty() { "<script>" } else { function.name.as_str() };
+    writeln!(output, "== {} ==", name).unwrap();
+
+    let chunk = &function.chunk;
+    for offset in 0..chunk.len() {
+        disassemble_instruction(chunk, offset, &mut output);
+    }
+
+    for offset in 0..chunk.len() {
+        if let OpCode::OpConstant(index) = chunk.get_code(offset).clone() {
+            if let Value::ValFunction(nested) = chunk.get_constant(index).clone() {
+                writeln!(output).unwrap();
+                output.push_str(disassemble_function(&nested).as_str());
+            }
+        }
+    }
+
+    output
+}
+
+pub fn disassemble_instruction(chunk: &Chunk, offset: usize, output: &mut String) {
+    let op = chunk.get_code(offset).clone();
+    let line = chunk.get_line(offset);
+    if offset > 0 && line == chunk.get_line(offset - 1) {
+        write!(output, "{:04}    | ", offset).unwrap();
+    } else {
+        write!(output, "{:04} {:>4} ", offset, line).unwrap();
+    }
+
+    //OpCode's debug output is `OpName(operand)`, which is split into aligned columns
+    let debug = format!("{:?}", op);
+    let (name, operand) = match debug.find('(') {
+        Some(paren) => (&debug[..paren], debug[paren + 1..debug.len() - 1].to_string()),
+        None => (debug.as_str(), String::new()),
+    };
+
+    let annotation = match op {
+        OpCode::OpConstant(index) | OpCode::OpDefineGlobal(index) | OpCode::OpGetGlobal(index) | OpCode::OpSetGlobal(index) => {
+            format!("'{}'", constant_string(chunk.get_constant(index).clone()))
+        }
+        OpCode::OpJump(jump) | OpCode::OpJumpIfFalse(jump) | OpCode::OpJumpIfTrue(jump) => format!("-> {:04}", offset + 1 + jump),
+        OpCode::OpLoop(jump) => format!("-> {:04}", offset + 1 - jump),
+        _ => String::new(),
+    };
+
+    let text = format!("{:<16} {:>4} {}", name, operand, annotation);
+    writeln!(output, "{}", text.trim_end()).unwrap();
+}
+
+fn constant_string(value: Value) -> String {
+    match SimValue::from_constant(&value) {
+        Some(value) => value.to_string(),
+        None => String::from("?"),
+    }
+}
@@ -0,0 +1,448 @@
+use std::fmt;
+use san_common::keycodes::HID_KEY_STRINGS;
+use crate::scanner::Scanner;
+use crate::token::TokenType;
+
+const INDENT: &str = "    ";
+//DuckyScript names of keys whose SanScript name differs, the first candidate known to the HID tables wins
+const KEY_ALIASES: [(&str, &[&str]); 21] = [
+    ("WINDOWS", &["GUI"]),
+    ("COMMAND", &["GUI"]),
+    ("CONTROL", &["CTRL"]),
+    ("OPTION", &["ALT"]),
+    ("ESC", &["ESCAPE"]),
+    ("DEL", &["DELETE"]),
+    ("BREAK", &["PAUSE"]),
+    ("UP", &["UP_ARROW", "UP"]),
+    ("UPARROW", &["UP_ARROW", "UP"]),
+    ("DOWN", &["DOWN_ARROW", "DOWN"]),
+    ("DOWNARROW", &["DOWN_ARROW", "DOWN"]),
+    ("LEFT", &["LEFT_ARROW", "LEFT"]),
+    ("LEFTARROW", &["LEFT_ARROW", "LEFT"]),
+    ("RIGHT", &["RIGHT_ARROW", "RIGHT"]),
+    ("RIGHTARROW", &["RIGHT_ARROW", "RIGHT"]),
+    ("PAGEUP", &["PAGE_UP"]),
+    ("PAGEDOWN", &["PAGE_DOWN"]),
+    ("CAPSLOCK", &["CAPS_LOCK"]),
+    ("NUMLOCK", &["NUM_LOCK"]),
+    ("PRINTSCREEN", &["PRINT_SCREEN"]),
+    ("APP", &["MENU", "APPLICATION"]),
+];
+//DuckyScript operators without a SanScript counterpart
+const UNSUPPORTED_OPERATORS: [&str; 7] = ["<<", ">>", "%", "^", "&", "|", "~"];
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Unsupported {
+    pub line: usize,
+    pub message: String,
+}
+
+impl fmt::Display for Unsupported {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
+pub struct Translation {
+    pub source: String,
+    //lines that were left in the output as comments
+    pub unsupported: Vec<Unsupported>,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Block {
+    If,
+    While,
+    Function,
+}
+
+impl Block {
+    fn end(&self) -> &'static str {
+        match self {
+            Block::If => "END_IF",
+            Block::While => "END_WHILE",
+            Block::Function => "END_FUNCTION",
+        }
+    }
+}
+
+//translates a Rubber Ducky script into SanScript, line by line
+pub fn translate(script: &str) -> Translation {
+    let mut translator = Translator::new();
+    for (index, line) in script.lines().enumerate() {
+        translator.line(index + 1, line);
+    }
+    translator.finish()
+}
+
+struct Translator {
+    output: String,
+    blocks: Vec<(Block, usize)>,
+    //delay DuckyScript waits after every command that sends keys
+    default_delay: u64,
+    //delay between the keys of the next STRING
+    string_delay: Option<u64>,
+    defines: Vec<(String, String)>,
+    //statements of the last command, for REPEAT
+    last: Option<Vec<String>>,
+    in_rem_block: bool,
+    blank_line: bool,
+    number: usize,
+    unsupported: Vec<Unsupported>,
+}
+
+impl Translator {
+    fn new() -> Translator {
+        Translator {
+            output: String::new(),
+            blocks: vec![],
+            default_delay: 0,
+            string_delay: None,
+            defines: vec![],
+            last: None,
+            in_rem_block: false,
+            blank_line: false,
+            number: 0,
+            unsupported: vec![],
+        }
+    }
+
+    fn finish(mut self) -> Translation {
+        while let Some((block, line)) = self.blocks.pop() {
+            self.report(line, format!("Missing {}", block.end()));
+            self.emit("}");
+        }
+        Translation { source: self.output, unsupported: self.unsupported }
+    }
+
+    fn line(&mut self, number: usize, raw: &str) {
+        self.number = number;
+        //trailing spaces are typed by STRING
+        let line = raw.trim_start();
+        if self.in_rem_block {
+            if line.trim() == "END_REM" {
+                self.in_rem_block = false;
+            } else {
+                self.comment(line.trim());
+            }
+            return;
+        }
+        if line.trim().is_empty() {
+            self.blank_line = !self.output.is_empty();
+            return;
+        }
+
+        let line = self.expand_defines(line);
+        let (command, argument) = match line.split_once(' ') {
+            Some((command, argument)) => (command, argument),
+            None => (line.as_str(), ""),
+        };
+        let result = match command {
+            "REM" => {
+                self.comment(argument.trim_end());
+                Ok(())
+            }
+            "REM_BLOCK" => {
+                self.in_rem_block = true;
+                if !argument.is_empty() {
+                    self.comment(argument);
+                }
+                Ok(())
+            }
+            "DEFINE" => self.define(argument),
+            "STRING" => self.string(argument, false),
+            "STRINGLN" => self.string(argument, true),
+            "STRING_DELAY" | "STRINGDELAY" => Translator::number(argument).map(|delay| self.string_delay = Some(delay)),
+            "DEFAULT_DELAY" | "DEFAULTDELAY" => self.set_default_delay(argument),
+            "DELAY" => Translator::expression(argument).map(|delay| self.command(vec![format!("sleep({});", delay)], false)),
+            "REPEAT" => self.repeat(argument),
+            "VAR" => self.assignment(argument.trim(), true),
+            "IF" => self.condition(argument, Block::If, "if"),
+            "ELSE" => self.else_branch(argument),
+            "WHILE" => self.condition(argument, Block::While, "while"),
+            "FUNCTION" => self.function(argument),
+            "RETURN" => Translator::expression(argument).map(|value| self.command(vec![format!("return {};", value)], false)),
+            "END_IF" => self.end_block(Block::If),
+            "END_WHILE" => self.end_block(Block::While),
+            "END_FUNCTION" => self.end_block(Block::Function),
+            _ if command.starts_with('$') => self.assignment(line.as_str(), false),
+            _ if line.trim_end().ends_with("()") && Translator::is_name(line.trim_end().trim_end_matches("()")) => {
+                self.command(vec![format!("{};", line.trim_end())], false);
+                Ok(())
+            }
+            _ => self.keys(line.as_str()),
+        };
+
+        if let Err(message) = result {
+            self.report(number, message);
+            self.comment(format!("untranslated: {}", raw.trim()).as_str());
+        }
+    }
+
+    //DEFINE is a plain text replacement that also applies inside STRING
+    fn define(&mut self, argument: &str) -> Result<(), String> {
+        match argument.split_once(' ') {
+            Some((name, value)) if name.starts_with('#') => {
+                self.defines.push((name.to_string(), value.to_string()));
+                Ok(())
+            }
+            _ => Err(String::from("DEFINE expects a #NAME and a value")),
+        }
+    }
+
+    fn expand_defines(&self, line: &str) -> String {
+        if line.starts_with("DEFINE ") {
+            return line.to_string();
+        }
+        self.defines.iter().fold(line.to_string(), |line, (name, value)| line.replace(name.as_str(), value.as_str()))
+    }
+
+    fn string(&mut self, text: &str, enter: bool) -> Result<(), String> {
+        if text.is_empty() {
+            return Err(String::from("STRING blocks are not supported, put the text on the STRING line"));
+        }
+        if text.contains('"') {
+            return Err(String::from("SanScript strings can't contain '\"'"));
+        }
+
+        let mut keys = format!("string_to_keys(\"{}\")", text);
+        if enter {
+            keys.push_str(" | ENTER");
+        }
+        let statement = match self.string_delay.take() {
+            Some(delay) => format!("inject_sequence({}, {}, 0);", keys, delay),
+            None => format!("inject_keys({});", keys),
+        };
+        self.command(vec![statement], true);
+        Ok(())
+    }
+
+    fn set_default_delay(&mut self, argument: &str) -> Result<(), String> {
+        if !self.blocks.is_empty() {
+            return Err(String::from("DEFAULT_DELAY is only supported outside of IF, WHILE and FUNCTION"));
+        }
+        self.default_delay = Translator::number(argument)?;
+        Ok(())
+    }
+
+    fn repeat(&mut self, argument: &str) -> Result<(), String> {
+        let count = Translator::number(argument)?;
+        let statements = self.last.clone().ok_or_else(|| String::from("REPEAT must follow a command"))?;
+        self.emit(format!("for (let i = 0; i < {}; i = i + 1) {{", count).as_str());
+        for statement in &statements {
+            self.emit(format!("{}{}", INDENT, statement).as_str());
+        }
+        self.emit("}");
+        Ok(())
+    }
+
+    //`VAR $name = value` declares, `$name = value` assigns
+    fn assignment(&mut self, argument: &str, declare: bool) -> Result<(), String> {
+        let (name, value) = argument.split_once('=').ok_or_else(|| String::from("Expected '=' in assignment"))?;
+        let name = Translator::variable(name.trim())?;
+        let value = Translator::expression(value)?;
+        let keyword = if declare { "let " } else { "" };
+        self.command(vec![format!("{}{} = {};", keyword, name, value)], false);
+        Ok(())
+    }
+
+    fn condition(&mut self, argument: &str, block: Block, keyword: &str) -> Result<(), String> {
+        let condition = match block {
+            Block::If => argument.trim().strip_suffix("THEN").ok_or_else(|| String::from("Expected THEN after the IF condition"))?,
+            _ => argument,
+        };
+        let condition = Translator::expression(condition)?;
+        self.emit(format!("{} ({}) {{", keyword, Translator::strip_parens(condition.as_str())).as_str());
+        self.open_block(block);
+        Ok(())
+    }
+
+    fn else_branch(&mut self, argument: &str) -> Result<(), String> {
+        if self.blocks.last().map(|(block, _)| *block) != Some(Block::If) {
+            return Err(String::from("ELSE without IF"));
+        }
+        let argument = argument.trim();
+        let line = if argument.is_empty() {
+            String::from("} else {")
+        } else {
+            let condition = argument
+                .strip_prefix("IF")
+                .and_then(|condition| condition.trim().strip_suffix("THEN"))
+                .ok_or_else(|| String::from("Expected ELSE or ELSE IF ... THEN"))?;
+            let condition = Translator::expression(condition)?;
+            format!("}} else if ({}) {{", Translator::strip_parens(condition.as_str()))
+        };
+        self.emit_at(self.blocks.len() - 1, line.as_str());
+        self.last = None;
+        Ok(())
+    }
+
+    fn function(&mut self, argument: &str) -> Result<(), String> {
+        let name = argument.trim().strip_suffix("()").filter(|name| Translator::is_name(name))
+            .ok_or_else(|| String::from("Expected FUNCTION name()"))?;
+        if !self.blocks.is_empty() {
+            return Err(String::from("FUNCTION is only supported at the top level"));
+        }
+        self.emit(format!("fn {}() {{", name).as_str());
+        self.open_block(Block::Function);
+        Ok(())
+    }
+
+    fn end_block(&mut self, block: Block) -> Result<(), String> {
+        match self.blocks.last() {
+            Some((open, _)) if *open == block => {
+                self.blocks.pop();
+                self.emit("}");
+                self.last = None;
+                Ok(())
+            }
+            _ => Err(format!("{} without a matching block", block.end())),
+        }
+    }
+
+    //combo lines like `GUI r` or `CTRL-ALT DELETE`
+    fn keys(&mut self, line: &str) -> Result<(), String> {
+        let mut keys = vec![];
+        for name in line.split(|c: char| c.is_whitespace() || c == '-').filter(|name| !name.is_empty()) {
+            keys.push(ducky_key(name).ok_or_else(|| format!("Unknown command or key '{}'", name))?);
+        }
+        self.command(vec![format!("inject_keys({});", keys.join(" + "))], true);
+        Ok(())
+    }
+
+    fn command(&mut self, mut statements: Vec<String>, sends_keys: bool) {
+        if sends_keys && self.default_delay > 0 {
+            statements.push(format!("sleep({});", self.default_delay));
+        }
+        for statement in &statements {
+            self.emit(statement.as_str());
+        }
+        self.last = Some(statements);
+    }
+
+    fn comment(&mut self, text: &str) {
+        let comment = if text.is_empty() { String::from("//") } else { format!("// {}", text) };
+        self.emit(comment.as_str());
+    }
+
+    fn emit(&mut self, line: &str) {
+        self.emit_at(self.blocks.len(), line);
+    }
+
+    fn emit_at(&mut self, depth: usize, line: &str) {
+        if self.blank_line {
+            self.output.push('\n');
+            self.blank_line = false;
+        }
+        self.output.push_str(format!("{}{}\n", INDENT.repeat(depth), line).as_str());
+    }
+
+    fn open_block(&mut self, block: Block) {
+        self.blocks.push((block, self.number));
+        self.last = None;
+    }
+
+    fn report(&mut self, line: usize, message: String) {
+        self.unsupported.push(Unsupported { line, message });
+    }
+
+    fn number(argument: &str) -> Result<u64, String> {
+        argument.trim().parse().map_err(|_| format!("Expected a number, found '{}'", argument.trim()))
+    }
+
+    fn is_name(name: &str) -> bool {
+        !name.is_empty() && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') && !name.starts_with(|c: char| c.is_ascii_digit())
+    }
+
+    //`$name` becomes `name`, unless that is a keyword or key name of SanScript
+    fn variable(name: &str) -> Result<String, String> {
+        let name = name.strip_prefix('$').ok_or_else(|| format!("Expected a variable, found '{}'", name))?;
+        if name.starts_with('_') {
+            return Err(format!("Internal variable '${}' has no SanScript counterpart", name));
+        }
+        if !Translator::is_name(name) {
+            return Err(format!("Invalid variable name '${}'", name));
+        }
+        let mut scanner = Scanner::new(name);
+        if scanner.scan_token().token_type == TokenType::Identifier {
+            Ok(name.to_string())
+        } else {
+            Ok(format!("{}_", name))
+        }
+    }
+
+    fn expression(expression: &str) -> Result<String, String> {
+        let expression = expression.trim();
+        if expression.is_empty() {
+            return Err(String::from("Expected an expression"));
+        }
+
+        let mut translated = String::new();
+        let mut rest = expression;
+        while let Some(c) = rest.chars().next() {
+            let (text, length) = match c {
+                '$' => {
+                    let length = 1 + rest[1..].find(|c: char| !(c.is_ascii_alphanumeric() || c == '_')).unwrap_or(rest.len() - 1);
+                    (Translator::variable(&rest[..length])?, length)
+                }
+                _ if rest.starts_with("&&") => (String::from("and"), 2),
+                _ if rest.starts_with("||") => (String::from("or"), 2),
+                _ if rest.starts_with("TRUE") => (String::from("true"), 4),
+                _ if rest.starts_with("FALSE") => (String::from("false"), 5),
+                _ => match UNSUPPORTED_OPERATORS.iter().find(|operator| rest.starts_with(*operator)) {
+                    Some(operator) => return Err(format!("Operator '{}' is not supported by SanScript", operator)),
+                    None => (c.to_string(), c.len_utf8()),
+                },
+            };
+            translated.push_str(text.as_str());
+            rest = &rest[length..];
+        }
+        Ok(translated)
+    }
+
+    //`IF ($a == 1) THEN` keeps its parentheses, but `if` brings its own
+    fn strip_parens(expression: &str) -> &str {
+        let inner = match expression.strip_prefix('(').and_then(|inner| inner.strip_suffix(')')) {
+            Some(inner) => inner,
+            None => return expression.trim(),
+        };
+        let mut depth = 0;
+        for c in inner.chars() {
+            match c {
+                '(' => depth += 1,
+                ')' if depth == 0 => return expression.trim(),
+                ')' => depth -= 1,
+                _ => ()
+            }
+        }
+        inner.trim()
+    }
+}
+
+//maps a DuckyScript key name like `r`, `ESC` or `UPARROW` onto its name in HID_KEY_STRINGS
+pub fn ducky_key(name: &str) -> Option<String> {
+    let upper = name.to_uppercase();
+    let candidates = match KEY_ALIASES.iter().find(|(alias, _)| *alias == upper) {
+        Some((_, names)) => names.iter().map(|name| name.to_string()).collect(),
+        None if upper.len() == 1 && upper.chars().all(|c| c.is_ascii_digit()) => vec![format!("NUM_{}", upper)],
+        None => vec![upper],
+    };
+    candidates.into_iter().find(|candidate| HID_KEY_STRINGS.contains(&candidate.as_str()))
+}
+
+//the reverse of ducky_key, `R` is written `r` and `UP_ARROW` is written `UP`
+pub fn ducky_name(key: &str) -> String {
+    if let Some(digit) = key.strip_prefix("NUM_").filter(|digit| digit.len() == 1) {
+        return digit.to_string();
+    }
+    if key.len() == 1 {
+        return key.to_lowercase();
+    }
+    if !key.contains('_') {
+        return key.to_string();
+    }
+    KEY_ALIASES
+        .iter()
+        .find(|(_, names)| names.contains(&key))
+        .map_or(key.to_string(), |(alias, _)| alias.to_string())
+}
@@ -0,0 +1,411 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::rc::Rc;
+use san_common::chunk::OpCode;
+use san_common::value::FunctionData;
+use crate::layout::KeyboardLayout;
+use crate::natives::NATIVES;
+use crate::simulator::{binary_op, string_to_keys, unary_op, SimValue};
+use crate::typing::TypingProfile;
+
+//every path through the program is followed until these limits are hit, after which the maximum is unbounded
+const PATH_LIMIT: usize = 256;
+const STEP_LIMIT: usize = 1_000_000;
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Estimate {
+    pub min: u64,
+    //None when some path can run for an unbounded amount of time
+    pub max: Option<u64>,
+}
+
+impl Estimate {
+    pub fn exact(&self) -> Option<u64> {
+        match self.max {
+            Some(max) if max == self.min => Some(max),
+            _ => None
+        }
+    }
+
+    fn merge(&mut self, min: u64, max: Option<u64>) {
+        self.min = self.min.min(min);
+        self.max = match (self.max, max) {
+            (Some(a), Some(b)) => Some(a.max(b)),
+            _ => None
+        };
+    }
+}
+
+impl fmt::Display for Estimate {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match (self.exact(), self.max) {
+            (Some(exact), _) => write!(f, "{} ms", exact),
+            (None, Some(max)) => write!(f, "{} - {} ms", self.min, max),
+            (None, None) => write!(f, "at least {} ms, no upper bound", self.min),
+        }
+    }
+}
+
+//values the estimator can't know statically are None
+type AbstractValue = Option<SimValue>;
+
+#[derive(Clone)]
+struct Frame {
+    function: Rc<FunctionData>,
+    ip: usize,
+    base: usize,
+}
+
+#[derive(Clone)]
+struct PathState {
+    stack: Vec<AbstractValue>,
+    frames: Vec<Frame>,
+    globals: HashMap<String, AbstractValue>,
+    min: u64,
+    max: Option<u64>,
+    //None once the profile was set to values the estimator can't know
+    typing: Option<TypingProfile>,
+}
+
+enum Step {
+    Continue,
+    Fork(PathState),
+    Finished,
+}
+
+//adds up sleeps and typing delays along every path of the compiled script
+pub fn estimate(function: FunctionData, layout: &KeyboardLayout) -> Estimate {
+    let mut globals = HashMap::new();
+    for (index, native) in NATIVES.iter().enumerate() {
+        globals.insert(native.name.to_string(), Some(SimValue::Native(index)));
+    }
+
+    let function = Rc::new(function);
+    let initial = PathState {
+        stack: vec![Some(SimValue::Function(function.clone()))],
+        frames: vec![Frame { function, ip: 0, base: 0 }],
+        globals,
+        min: 0,
+        max: Some(0),
+        typing: Some(TypingProfile::default()),
+    };
+
+    let mut estimate: Option<Estimate> = None;
+    let mut pending = vec![initial];
+    let mut paths = 1;
+    let mut steps = 0;
+
+    while let Some(mut state) = pending.pop() {
+        loop {
+            steps += 1;
+            if steps > STEP_LIMIT {
+                state.max = None;
+                break;
+            }
+
+            match state.step(layout) {
+                Step::Continue => {}
+                Step::Fork(mut other) => {
+                    paths += 1;
+                    if paths > PATH_LIMIT {
+                        other.max = None;
+                        merge(&mut estimate, &other);
+                    } else {
+                        pending.push(other);
+                    }
+                }
+                Step::Finished => break,
+            }
+        }
+
+        merge(&mut estimate, &state);
+    }
+
+    estimate.unwrap_or(Estimate { min: 0, max: Some(0) })
+}
+
+fn merge(estimate: &mut Option<Estimate>, state: &PathState) {
+    match estimate {
+        Some(estimate) => estimate.merge(state.min, state.max),
+        None => *estimate = Some(Estimate { min: state.min, max: state.max }),
+    }
+}
+
+impl PathState {
+    fn step(&mut self, layout: &KeyboardLayout) -> Step {
+        let frame = self.frames.last_mut().expect("Estimator has no call frame!");
+        let op = frame.function.chunk.get_code(frame.ip).clone();
+        frame.ip += 1;
+
+        match op {
+            OpCode::OpConstant(index) => {
+                let constant = self.chunk_constant(index);
+                self.stack.push(constant);
+            }
+            OpCode::OpNil => self.stack.push(Some(SimValue::Nil)),
+            OpCode::OpTrue => self.stack.push(Some(SimValue::Bool(true))),
+            OpCode::OpFalse => self.stack.push(Some(SimValue::Bool(false))),
+            OpCode::OpPop | OpCode::OpPrint => { self.stack.pop(); }
+            OpCode::OpGetLocal(slot) => {
+                let value = self.stack[self.base() + slot].clone();
+                self.stack.push(value);
+            }
+            OpCode::OpSetLocal(slot) => {
+                let base = self.base();
+                self.stack[base + slot] = self.peek(0).clone();
+            }
+            OpCode::OpDefineGlobal(index) => {
+                let value = self.stack.pop().flatten();
+                if let Some(SimValue::String(name)) = self.chunk_constant(index) {
+                    self.globals.insert(name, value);
+                }
+            }
+            OpCode::OpGetGlobal(index) => {
+                let value = match self.chunk_constant(index) {
+                    Some(SimValue::String(name)) => match self.globals.get(&name) {
+                        Some(value) => value.clone(),
+                        //the payload would stop here with a runtime error
+                        None => return Step::Finished,
+                    },
+                    _ => None
+                };
+                self.stack.push(value);
+            }
+            OpCode::OpSetGlobal(index) => {
+                let value = self.peek(0).clone();
+                if let Some(SimValue::String(name)) = self.chunk_constant(index) {
+                    self.globals.insert(name, value);
+                }
+            }
+            OpCode::OpEqual => {
+                let b = self.stack.pop().flatten();
+                let a = self.stack.pop().flatten();
+                self.stack.push(match (a, b) {
+                    (Some(a), Some(b)) => Some(SimValue::Bool(a.equals(&b))),
+                    _ => None
+                });
+            }
+            OpCode::OpGreater | OpCode::OpLess | OpCode::OpAdd | OpCode::OpSubtract
+            | OpCode::OpMultiply | OpCode::OpDivide | OpCode::OpPipe => {
+                let b = self.stack.pop().flatten();
+                let a = self.stack.pop().flatten();
+                let value = match (a, b) {
+                    (Some(a), Some(b)) => match binary_op(op, a, b) {
+                        Ok(value) => Some(value),
+                        Err(_) => return Step::Finished,
+                    },
+                    _ => None
+                };
+                self.stack.push(value);
+            }
+            OpCode::OpNot | OpCode::OpNegate => {
+                let value = match self.stack.pop().flatten() {
+                    Some(value) => match unary_op(op, value) {
+                        Ok(value) => Some(value),
+                        Err(_) => return Step::Finished,
+                    },
+                    None => None
+                };
+                self.stack.push(value);
+            }
+            OpCode::OpJump(offset) => self.frame_mut().ip += offset,
+            OpCode::OpJumpIfFalse(offset) | OpCode::OpJumpIfTrue(offset) => {
+                let jump_if = matches!(op, OpCode::OpJumpIfTrue(_));
+                match self.peek(0) {
+                    Some(condition) => {
+                        if condition.is_falsey() != jump_if {
+                            self.frame_mut().ip += offset;
+                        }
+                    }
+                    None => {
+                        let mut other = self.clone();
+                        other.frame_mut().ip += offset;
+                        return Step::Fork(other);
+                    }
+                }
+            }
+            OpCode::OpLoop(offset) => self.frame_mut().ip -= offset,
+            OpCode::OpCall(arg_count) => return self.call(arg_count, layout),
+            OpCode::OpReturn => {
+                let result = self.stack.pop().flatten();
+                let frame = self.frames.pop().expect("Estimator has no call frame!");
+                self.stack.truncate(frame.base);
+                if self.frames.is_empty() {
+                    return Step::Finished;
+                }
+                self.stack.push(result);
+            }
+            #[allow(unreachable_patterns)]
+            _ => {
+                self.max = None;
+                return Step::Finished;
+            }
+        }
+
+        Step::Continue
+    }
+
+    fn call(&mut self, arg_count: usize, layout: &KeyboardLayout) -> Step {
+        let callee = self.peek(arg_count).clone();
+        match callee {
+            Some(SimValue::Function(function)) => {
+                if function.arity != arg_count {
+                    return Step::Finished;
+                }
+                let base = self.stack.len() - arg_count - 1;
+                self.frames.push(Frame { function, ip: 0, base });
+            }
+            Some(SimValue::Native(index)) => {
+                let args = self.stack.split_off(self.stack.len() - arg_count);
+                self.stack.pop();
+                match self.call_native(NATIVES[index].name, args, layout) {
+                    Some(result) => self.stack.push(result),
+                    None => return Step::Finished,
+                }
+            }
+            //an unknown callee can do anything, so only the time spent so far is certain
+            _ => {
+                self.stack.truncate(self.stack.len() - arg_count - 1);
+                self.stack.push(None);
+                self.max = None;
+            }
+        }
+
+        Step::Continue
+    }
+
+    //returns None when the native would stop the payload
+    fn call_native(&mut self, name: &str, args: Vec<AbstractValue>, layout: &KeyboardLayout) -> Option<AbstractValue> {
+        match name {
+            "sleep" => self.add_duration(millis(&args[0]), Some(1)),
+            "inject_sequence" => {
+                let key_count = match &args[0] {
+                    Some(SimValue::Keys(chords)) => Some(chords.len() as u64),
+                    _ => None
+                };
+                let hold = match &args[2] {
+                    Some(SimValue::Nil) => Some(0),
+                    hold => millis(hold),
+                };
+                match (&args[1], hold) {
+                    (Some(SimValue::Nil), Some(hold)) => self.add_typing(key_count, hold),
+                    (delay, Some(hold)) => self.add_duration(millis(delay).map(|delay| delay + hold), key_count),
+                    _ => self.max = None,
+                }
+            }
+            "inject_media" => {
+                let times = match &args[1] {
+                    Some(SimValue::Nil) => Some(1),
+                    times => millis(times),
+                };
+                match &args[2] {
+                    Some(SimValue::Nil) => self.add_typing(times, 0),
+                    delay => self.add_duration(millis(delay), times),
+                }
+            }
+            "type_text" => {
+                let key_count = match &args[0] {
+                    Some(SimValue::String(text)) => string_to_keys(layout, text).ok().map(|keys| keys.len() as u64),
+                    _ => None
+                };
+                self.add_typing(key_count, 0);
+            }
+            "__set_typing" => {
+                self.typing = match (millis(&args[0]), millis(&args[1]), millis(&args[2])) {
+                    (Some(speed), Some(jitter), Some(seed)) => Some(TypingProfile { speed, jitter, seed }),
+                    _ => None
+                };
+            }
+            "string_to_keys" => {
+                return match &args[0] {
+                    Some(SimValue::String(text)) => string_to_keys(layout, text).ok().map(|keys| Some(SimValue::Keys(keys))),
+                    _ => Some(None),
+                };
+            }
+            "__assert_fail" => return None,
+            _ => ()
+        }
+
+        Some(Some(SimValue::Nil))
+    }
+
+    fn add_duration(&mut self, duration: Option<u64>, count: Option<u64>) {
+        self.add_range(duration, duration, count);
+    }
+
+    //keys typed with the profile take anywhere between its shortest and longest delay
+    fn add_typing(&mut self, count: Option<u64>, hold: u64) {
+        let typing = self.typing;
+        self.add_range(typing.map(|typing| typing.min_delay() + hold), typing.map(|typing| typing.max_delay() + hold), count);
+    }
+
+    fn add_range(&mut self, min: Option<u64>, max: Option<u64>, count: Option<u64>) {
+        match (min, max, count) {
+            (Some(min), Some(max), Some(count)) => {
+                self.min += min * count;
+                self.max = self.max.map(|total| total + max * count);
+            }
+            _ => self.max = None,
+        }
+    }
+
+    fn chunk_constant(&self, index: usize) -> AbstractValue {
+        let constant = self.frames.last().expect("Estimator has no call frame!").function.chunk.get_constant(index).clone();
+        SimValue::from_constant(&constant)
+    }
+
+    fn frame_mut(&mut self) -> &mut Frame {
+        self.frames.last_mut().expect("Estimator has no call frame!")
+    }
+
+    fn base(&self) -> usize {
+        self.frames.last().expect("Estimator has no call frame!").base
+    }
+
+    fn peek(&self, distance: usize) -> &AbstractValue {
+        &self.stack[self.stack.len() - 1 - distance]
+    }
+}
+
+fn millis(value: &AbstractValue) -> Option<u64> {
+    match value {
+        Some(SimValue::Number(ms)) if *ms >= 0.0 => Some(*ms as u64),
+        _ => None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::test_support::{compile, us};
+    use super::{estimate, Estimate};
+
+    fn estimate_source(source: &str) -> Estimate {
+        estimate(compile(source).expect("source does not compile"), us())
+    }
+
+    #[test]
+    fn sleeps_and_sequences_add_up_through_calls() {
+        let source = "fn pause(ms) { sleep(ms); }\npause(100);\ninject_sequence(string_to_keys(\"ab\"), 10, 5);\n";
+        assert_eq!(estimate_source(source), Estimate { min: 130, max: Some(130) });
+    }
+
+    #[test]
+    fn left_out_typing_delays_take_the_range_of_the_profile() {
+        let source = "typing { speed: 20ms, jitter: 5ms, seed: 1 }\ninject_sequence(string_to_keys(\"ab\"));\ninject_sequence(string_to_keys(\"a\"), 10);\n";
+        assert_eq!(estimate_source(source), Estimate { min: 40, max: Some(60) });
+    }
+
+    #[test]
+    fn endless_loops_have_no_upper_bound() {
+        let estimate = estimate_source("sleep(5);\nwhile (true) { sleep(10); }\n");
+        assert_eq!(estimate.max, None);
+        assert!(estimate.min >= 5);
+    }
+
+    #[test]
+    fn display_shows_exact_values_and_ranges() {
+        assert_eq!(Estimate { min: 10, max: Some(10) }.to_string(), "10 ms");
+        assert_eq!(Estimate { min: 10, max: Some(20) }.to_string(), "10 - 20 ms");
+        assert_eq!(Estimate { min: 10, max: None }.to_string(), "at least 10 ms, no upper bound");
+    }
+}
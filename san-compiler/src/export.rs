@@ -0,0 +1,115 @@
+use san_common::value::{FunctionData, Value};
+use crate::ducky::ducky_name;
+use crate::layout::{key_name, KeyboardLayout};
+use crate::optimizer::decode;
+use crate::trace::{Trace, TraceEvent};
+
+//delays up to this long right after a key are typing and hold delays, a Ducky types at its own speed
+pub const TYPING_DELAY_LIMIT: u64 = 100;
+
+//without jumps or functions a program runs the same way every time, so its run is a translation
+pub fn is_straight_line(function: &FunctionData) -> bool {
+    decode(&function.chunk)
+        .iter()
+        .all(|instruction| instruction.target.is_none() && !matches!(instruction.constant, Some(Value::ValFunction(_))))
+}
+
+//expects the optimized program, so constant conditions are already gone, and the trace of its run
+pub fn export_ducky(function: &FunctionData, trace: &Trace, layout: &KeyboardLayout, source_name: &str) -> Result<String, String> {
+    let mut script = format!("REM Exported from {} by sanc\n", source_name);
+    if !is_straight_line(function) {
+        script.push_str("REM Recorded from a run in the simulator, loops and function calls are unrolled\n");
+    }
+    script.push_str(trace_to_ducky(trace, layout)?.as_str());
+    Ok(script)
+}
+
+//typed characters are collected into STRING lines, other chords become combo lines like `GUI r`
+pub fn trace_to_ducky(trace: &Trace, layout: &KeyboardLayout) -> Result<String, String> {
+    let mut writer = DuckyWriter::new();
+    //a report that follows another one without a release in between comes from keys held with key_down
+    let mut pressed = false;
+    for event in &trace.events {
+        match event {
+            TraceEvent::Delay(ms) => writer.delay += ms,
+            TraceEvent::KeyReport(keys) if keys.is_empty() => {
+                writer.key_event();
+                pressed = false;
+            }
+            TraceEvent::KeyReport(_) if pressed => {
+                return Err(String::from("DuckyScript 1.0 can't hold keys, the payload uses key_down or hold"));
+            }
+            TraceEvent::KeyReport(keys) => {
+                writer.key_event();
+                pressed = true;
+                match Trace::render_chord(keys, layout) {
+                    Some('\n') => writer.line(String::from("ENTER")),
+                    Some('\t') => writer.line(String::from("TAB")),
+                    Some(c) => writer.text.push(c),
+                    None => writer.line(combo(keys)?),
+                }
+            }
+            TraceEvent::Print(value) => {
+                writer.flush_delay();
+                writer.line(format!("REM {}", value));
+                writer.after_key = false;
+            }
+            TraceEvent::MouseMove(..) | TraceEvent::MouseDown(_) | TraceEvent::MouseUp
+            | TraceEvent::MouseScroll(..) | TraceEvent::MouseClick(_) | TraceEvent::MouseMoveTo(..) => {
+                return Err(String::from("DuckyScript 1.0 has no mouse commands, the payload uses the mouse"));
+            }
+            TraceEvent::Media(_) => return Err(String::from("DuckyScript 1.0 has no media keys, the payload uses inject_media")),
+        }
+    }
+    writer.flush_delay();
+    writer.flush_text();
+    Ok(writer.lines.iter().map(|line| format!("{}\n", line)).collect())
+}
+
+fn combo(keys: &[u8]) -> Result<String, String> {
+    let names = keys
+        .iter()
+        .map(|code| key_name(*code).map(ducky_name).ok_or_else(|| format!("Key 0x{:02X} has no DuckyScript name", code)))
+        .collect::<Result<Vec<String>, String>>()?;
+    Ok(names.join(" "))
+}
+
+struct DuckyWriter {
+    lines: Vec<String>,
+    //characters typed since the last line
+    text: String,
+    //delay since the last event
+    delay: u64,
+    after_key: bool,
+}
+
+impl DuckyWriter {
+    fn new() -> DuckyWriter {
+        DuckyWriter { lines: vec![], text: String::new(), delay: 0, after_key: false }
+    }
+
+    fn key_event(&mut self) {
+        self.flush_delay();
+        self.after_key = true;
+    }
+
+    fn flush_delay(&mut self) {
+        let delay = std::mem::take(&mut self.delay);
+        if delay == 0 || (self.after_key && delay <= TYPING_DELAY_LIMIT) {
+            return;
+        }
+        self.line(format!("DELAY {}", delay));
+    }
+
+    fn flush_text(&mut self) {
+        if !self.text.is_empty() {
+            let text = std::mem::take(&mut self.text);
+            self.lines.push(format!("STRING {}", text));
+        }
+    }
+
+    fn line(&mut self, line: String) {
+        self.flush_text();
+        self.lines.push(line);
+    }
+}
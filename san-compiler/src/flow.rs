@@ -0,0 +1,48 @@
+use std::collections::HashSet;
+use san_common::chunk::OpCode;
+use crate::optimizer::{constant_value, Instruction};
+
+//marks the instructions that can run at all, following the jumps the way the VM does
+pub fn reachable(instructions: &[Instruction]) -> Vec<bool> {
+    let targets: HashSet<usize> = instructions.iter().filter_map(|instruction| instruction.target).collect();
+    let index_of = |id: usize| instructions.partition_point(|other| other.id < id);
+    let mut reachable = vec![false; instructions.len()];
+    let mut pending = vec![0];
+
+    while let Some(index) = pending.pop() {
+        if index >= instructions.len() || reachable[index] {
+            continue;
+        }
+        reachable[index] = true;
+
+        let instruction = &instructions[index];
+        let target = instruction.target.map(index_of);
+        match instruction.op {
+            OpCode::OpReturn => (),
+            OpCode::OpJump(_) | OpCode::OpLoop(_) => pending.extend(target),
+            OpCode::OpJumpIfFalse(_) | OpCode::OpJumpIfTrue(_) => {
+                let jumps_if_falsey = matches!(instruction.op, OpCode::OpJumpIfFalse(_));
+                match constant_condition(instructions, index, &targets) {
+                    Some(falsey) if falsey == jumps_if_falsey => pending.extend(target),
+                    Some(_) => pending.push(index + 1),
+                    None => {
+                        pending.push(index + 1);
+                        pending.extend(target);
+                    }
+                }
+            }
+            _ => pending.push(index + 1),
+        }
+    }
+
+    reachable
+}
+
+//a condition pushed right before the jump is known, unless another jump lands on the jump itself
+//with a different value on the stack, like the end of an `and` chain
+pub fn constant_condition(instructions: &[Instruction], index: usize, targets: &HashSet<usize>) -> Option<bool> {
+    if index == 0 || targets.contains(&instructions[index].id) {
+        return None;
+    }
+    constant_value(&instructions[index - 1]).map(|value| value.is_falsey())
+}
@@ -0,0 +1,228 @@
+use crate::scanner::Scanner;
+use crate::token::{Token, TokenType};
+
+const INDENT: &str = "    ";
+//lines longer than this get their top level `|` chains wrapped
+pub const MAX_WIDTH: usize = 100;
+
+pub fn format_source(source: &str) -> Result<String, String> {
+    let mut scanner = Scanner::with_trivia(source);
+    let mut tokens = vec![];
+    loop {
+        let token = scanner.scan_token();
+        match &token.token_type {
+            TokenType::Error(message) => return Err(format!("[line {}] Error: {}", token.line, message)),
+            TokenType::EOF => break,
+            TokenType::Whitespace => continue,
+            _ => tokens.push(token),
+        }
+    }
+
+    let mut formatter = Formatter::new(source);
+    formatter.format(&tokens);
+    Ok(formatter.output)
+}
+
+struct Formatter<'a> {
+    source: &'a str,
+    output: String,
+    line: String,
+    indent: usize,
+    //set when the current line continues a statement that was broken by a comment
+    continuation: bool,
+    paren_depth: usize,
+    //inside a `#[...]` attribute, which gets a line of its own
+    attribute: bool,
+    previous: Option<TokenType>,
+    unary_minus: bool,
+    //offsets in the current line where a top level `|` chain may be broken
+    pipe_breaks: Vec<usize>,
+    newlines: usize,
+}
+
+impl<'a> Formatter<'a> {
+    fn new(source: &'a str) -> Formatter<'a> {
+        Formatter {
+            source,
+            output: String::new(),
+            line: String::new(),
+            indent: 0,
+            continuation: false,
+            paren_depth: 0,
+            attribute: false,
+            previous: None,
+            unary_minus: false,
+            pipe_breaks: vec![],
+            newlines: 0,
+        }
+    }
+
+    fn format(&mut self, tokens: &[Token]) {
+        for (index, token) in tokens.iter().enumerate() {
+            match token.token_type {
+                TokenType::Newline => {
+                    self.newlines += 1;
+                    continue;
+                }
+                TokenType::Comment => {
+                    let comment = token.get_token_string(self.source).trim_end().to_string();
+                    if self.line.is_empty() {
+                        self.blank_line_if_needed();
+                    } else {
+                        self.line.push(' ');
+                    }
+                    self.line.push_str(comment.as_str());
+                    let continuation = !self.at_statement_end();
+                    self.flush(continuation);
+                    self.newlines = 0;
+                    continue;
+                }
+                _ => ()
+            }
+
+            let next = tokens[index + 1..]
+                .iter()
+                .find(|next| !matches!(next.token_type, TokenType::Newline | TokenType::Comment))
+                .map(|next| next.token_type.clone());
+
+            if self.line.is_empty() && token.token_type != TokenType::RightBrace {
+                self.blank_line_if_needed();
+            }
+            self.newlines = 0;
+            self.token(token, next);
+        }
+
+        if !self.line.is_empty() {
+            self.flush(false);
+        }
+    }
+
+    fn token(&mut self, token: &Token, next: Option<TokenType>) {
+        let text = token.get_token_string(self.source);
+        match token.token_type {
+            TokenType::LeftBrace => {
+                if !self.line.is_empty() {
+                    self.line.push(' ');
+                }
+                self.line.push('{');
+                self.flush(false);
+                self.indent += 1;
+            }
+            TokenType::RightBrace => {
+                if !self.line.is_empty() {
+                    self.flush(false);
+                }
+                self.indent = self.indent.saturating_sub(1);
+                self.line.push('}');
+                if next != Some(TokenType::Else) {
+                    self.flush(false);
+                }
+            }
+            TokenType::Semicolon => {
+                self.line.push(';');
+                if self.paren_depth == 0 {
+                    self.flush(false);
+                }
+            }
+            _ => {
+                if let Some(previous) = &self.previous {
+                    if !self.line.is_empty() && self.needs_space(previous, &token.token_type) {
+                        if token.token_type == TokenType::Pipe && self.paren_depth == 0 {
+                            self.pipe_breaks.push(self.line.len());
+                        }
+                        self.line.push(' ');
+                    }
+                }
+                self.line.push_str(text.as_str());
+
+                match token.token_type {
+                    TokenType::LeftParen => self.paren_depth += 1,
+                    TokenType::RightParen => self.paren_depth = self.paren_depth.saturating_sub(1),
+                    TokenType::Hash => self.attribute = true,
+                    TokenType::RightBracket if self.attribute => {
+                        self.attribute = false;
+                        self.flush(false);
+                    }
+                    _ => ()
+                }
+            }
+        }
+
+        self.unary_minus = token.token_type == TokenType::Minus && !self.previous.as_ref().map_or(false, Formatter::ends_operand);
+        self.previous = Some(token.token_type.clone());
+    }
+
+    fn needs_space(&self, previous: &TokenType, current: &TokenType) -> bool {
+        match (previous, current) {
+            (_, TokenType::RightParen) | (_, TokenType::Comma) | (_, TokenType::Colon) | (_, TokenType::Semicolon) | (_, TokenType::Dot) => false,
+            (TokenType::Dot, _) | (TokenType::LeftParen, _) | (TokenType::Bang, _) => false,
+            (TokenType::Hash, _) | (TokenType::LeftBracket, _) | (_, TokenType::RightBracket) => false,
+            (TokenType::Target, TokenType::LeftParen) => false,
+            //unit suffixes like the `ms` of `30ms`
+            (TokenType::Number, TokenType::Identifier) => false,
+            (TokenType::Minus, _) if self.unary_minus => false,
+            (TokenType::Identifier, TokenType::LeftParen) | (TokenType::RightParen, TokenType::LeftParen) => false,
+            _ => true
+        }
+    }
+
+    fn ends_operand(token_type: &TokenType) -> bool {
+        matches!(token_type, TokenType::Identifier | TokenType::Number | TokenType::String | TokenType::HidKey
+            | TokenType::MouseButton | TokenType::MediaKey | TokenType::True | TokenType::False | TokenType::Nil | TokenType::Target | TokenType::RightParen)
+    }
+
+    fn at_statement_end(&self) -> bool {
+        matches!(self.previous, None | Some(TokenType::Semicolon) | Some(TokenType::LeftBrace) | Some(TokenType::RightBrace))
+    }
+
+    fn blank_line_if_needed(&mut self) {
+        if self.newlines >= 2 && !self.output.is_empty() && !self.output.ends_with("{\n") && !self.output.ends_with("\n\n") {
+            self.output.push('\n');
+        }
+    }
+
+    fn flush(&mut self, continuation: bool) {
+        let depth = self.indent + if self.continuation { 1 } else { 0 };
+        let indent = INDENT.repeat(depth);
+        let line = std::mem::take(&mut self.line);
+        let breaks = std::mem::take(&mut self.pipe_breaks);
+
+        if indent.len() + line.chars().count() > MAX_WIDTH && !breaks.is_empty() {
+            let wrapped_indent = INDENT.repeat(depth + 1);
+            let mut start = 0;
+            for end in breaks {
+                let prefix = if start == 0 { &indent } else { &wrapped_indent };
+                self.output.push_str(format!("{}{}\n", prefix, line[start..end].trim()).as_str());
+                start = end;
+            }
+            self.output.push_str(format!("{}{}\n", wrapped_indent, line[start..].trim()).as_str());
+        } else {
+            self.output.push_str(format!("{}{}\n", indent, line).as_str());
+        }
+
+        self.continuation = continuation;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::format_source;
+
+    const UNFORMATTED: &str = "//greets\nfn f(x){\n//check\nif(x>1){print x;}else{print -x;}\n}\n\n\n\nlet a=1;\n";
+    const FORMATTED: &str = "//greets\nfn f(x) {\n    //check\n    if (x > 1) {\n        print x;\n    } else {\n        print -x;\n    }\n}\n\nlet a = 1;\n";
+
+    #[test]
+    fn formats_blocks_operators_and_comments() {
+        assert_eq!(format_source(UNFORMATTED).unwrap(), FORMATTED);
+    }
+
+    #[test]
+    fn formatting_is_idempotent() {
+        assert_eq!(format_source(FORMATTED).unwrap(), FORMATTED);
+    }
+
+    #[test]
+    fn scanner_errors_are_reported() {
+        assert!(format_source("let a = \"abc;\n").is_err());
+    }
+}
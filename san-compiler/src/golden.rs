@@ -0,0 +1,195 @@
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use san_common::value::FunctionType;
+use crate::compiler::Compiler;
+use crate::diagnostic::{Diagnostic, Severity};
+use crate::layout::KeyboardLayout;
+use crate::params::Params;
+use crate::simulator::{SimError, SimValue, Simulator};
+use crate::trace::Trace;
+
+pub const EXPECTED_EXTENSION: &str = "expected";
+
+pub enum GoldenResult {
+    Passed,
+    Blessed,
+    Missing,
+    //the payload doesn't compile, nothing is compared or blessed
+    CompileError(Vec<Diagnostic>),
+    Failed { line: usize, expected: String, actual: String },
+}
+
+//collects every .san file under the given path, or the path itself if it is a source file
+pub fn find_sources(path: &Path) -> io::Result<Vec<PathBuf>> {
+    let mut sources = vec![];
+    if path.is_file() {
+        sources.push(path.to_path_buf());
+        return Ok(sources);
+    }
+
+    for entry in fs::read_dir(path)? {
+        let entry_path = entry?.path();
+        if entry_path.is_dir() {
+            sources.extend(find_sources(&entry_path)?);
+        } else if entry_path.extension().map_or(false, |extension| extension == "san") {
+            sources.push(entry_path);
+        }
+    }
+
+    sources.sort();
+    Ok(sources)
+}
+
+//compiles and simulates a payload, producing the text that gets compared against its .expected file
+pub fn golden_output(source_path: &Path, source: &str, params: &Params, layout: &'static KeyboardLayout) -> Result<String, Vec<Diagnostic>> {
+    let mut compiler = Compiler::new(source, FunctionType::Script);
+    compiler.set_path(source_path);
+    compiler.set_params(params.clone());
+    compiler.set_quiet(true);
+    let function = match compiler.compile() {
+        Some(function) => function,
+        None => return Err(compiler.diagnostics().iter().filter(|diagnostic| diagnostic.severity == Severity::Error).cloned().collect()),
+    };
+
+    let mut simulator = Simulator::new(layout);
+    let result = simulator.run(function);
+
+    let mut output = format!("# text ({})\n{}\n", layout.name, simulator.trace.render_text(layout));
+    output.push_str(format!("# trace\n{}", simulator.trace).as_str());
+    if let Err(e) = result {
+        output.push_str(format!("# error\n{}\n", e).as_str());
+    }
+    output.push_str(format!("# duration\n{} ms\n", simulator.trace.duration()).as_str());
+    Ok(output)
+}
+
+//runs the script with its test blocks compiled in, then every test against the resulting globals
+pub fn run_source_tests(source_path: &Path, source: &str, params: &Params, layout: &'static KeyboardLayout) -> Option<Vec<(String, Result<(), SimError>)>> {
+    let mut compiler = Compiler::new(source, FunctionType::Script);
+    compiler.set_path(source_path);
+    compiler.set_params(params.clone());
+    compiler.set_include_tests(true);
+    let function = compiler.compile()?;
+
+    let mut simulator = Simulator::new(layout);
+    let setup = simulator.run(function);
+    let mut results = vec![];
+    for test in compiler.tests() {
+        let result = match &setup {
+            Ok(_) => {
+                simulator.trace = Trace::new();
+                simulator.call_value(SimValue::Function(Rc::new(test.function.clone())), vec![]).map(|_| ())
+            }
+            Err(e) => Err(SimError { message: format!("Script failed before tests ran: {}", e.message), line: e.line }),
+        };
+        results.push((test.name.clone(), result));
+    }
+
+    Some(results)
+}
+
+pub fn check_source(source_path: &Path, params: &Params, layout: &'static KeyboardLayout, bless: bool) -> io::Result<GoldenResult> {
+    let source = fs::read_to_string(source_path)?;
+    let actual = match golden_output(source_path, source.as_str(), params, layout) {
+        Ok(actual) => actual,
+        Err(diagnostics) => return Ok(GoldenResult::CompileError(diagnostics)),
+    };
+    let expected_path = source_path.with_extension(EXPECTED_EXTENSION);
+
+    if bless {
+        fs::write(&expected_path, actual)?;
+        return Ok(GoldenResult::Blessed);
+    }
+
+    let expected = match fs::read_to_string(&expected_path) {
+        Ok(expected) => expected,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(GoldenResult::Missing),
+        Err(e) => return Err(e),
+    };
+
+    if expected == actual {
+        return Ok(GoldenResult::Passed);
+    }
+
+    let mut expected_lines = expected.lines();
+    let mut actual_lines = actual.lines();
+    let mut line = 1;
+    loop {
+        match (expected_lines.next(), actual_lines.next()) {
+            (Some(e), Some(a)) if e == a => line += 1,
+            (e, a) => {
+                return Ok(GoldenResult::Failed {
+                    line,
+                    expected: e.unwrap_or("<end of file>").to_string(),
+                    actual: a.unwrap_or("<end of file>").to_string(),
+                });
+            }
+        }
+    }
+}
+
+//runs every payload under the path and returns whether all of them matched their expected output
+pub fn run_golden_tests(path: &Path, layout: &'static KeyboardLayout, bless: bool) -> io::Result<bool> {
+    let sources = find_sources(path)?;
+    let mut failed = 0;
+
+    for source_path in &sources {
+        let source = fs::read_to_string(source_path)?;
+        let params = Params::from_config(source_path)?;
+        let tests = match run_source_tests(source_path, source.as_str(), &params, layout) {
+            Some(tests) => tests,
+            None => {
+                failed += 1;
+                println!("FAIL    {} (compile error)", source_path.display());
+                continue;
+            }
+        };
+
+        let mut tests_failed = false;
+        for (name, result) in &tests {
+            match result {
+                Ok(_) => println!("PASS    {} :: {}", source_path.display(), name),
+                Err(e) => {
+                    tests_failed = true;
+                    println!("FAIL    {} :: {}", source_path.display(), name);
+                    println!("        {}", e);
+                }
+            }
+        }
+        if tests_failed {
+            failed += 1;
+            continue;
+        }
+
+        match check_source(source_path, &params, layout, bless)? {
+            //libraries that only carry test blocks have no payload output to compare
+            GoldenResult::Missing if !tests.is_empty() => {}
+            GoldenResult::Passed => println!("PASS    {}", source_path.display()),
+            GoldenResult::Blessed => println!("BLESSED {}", source_path.display()),
+            GoldenResult::Missing => {
+                failed += 1;
+                println!("MISSING {} (run with --bless to create it)", source_path.with_extension(EXPECTED_EXTENSION).display());
+            }
+            GoldenResult::CompileError(diagnostics) => {
+                failed += 1;
+                println!("FAIL    {} (compile error)", source_path.display());
+                for diagnostic in &diagnostics {
+                    println!("        {}", diagnostic);
+                }
+            }
+            GoldenResult::Failed { line, expected, actual } => {
+                failed += 1;
+                println!("FAIL    {}", source_path.display());
+                println!("        line {}:", line);
+                println!("        expected: {}", expected);
+                println!("        actual:   {}", actual);
+            }
+        }
+    }
+
+    println!();
+    println!("{} payloads, {} passed, {} failed", sources.len(), sources.len() - failed, failed);
+    Ok(failed == 0)
+}
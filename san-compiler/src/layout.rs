@@ -0,0 +1,206 @@
+use san_common::keycodes::{hid_string_to_code, mouse_string_to_code, HID_KEY_STRINGS, MOUSE_BUTTON_STRINGS};
+
+//usage ids from the HID keyboard/keypad page that every layout shares
+pub const KEY_A: u8 = 0x04;
+pub const KEY_1: u8 = 0x1E;
+pub const KEY_0: u8 = 0x27;
+pub const KEY_ENTER: u8 = 0x28;
+pub const KEY_ESCAPE: u8 = 0x29;
+pub const KEY_BACKSPACE: u8 = 0x2A;
+pub const KEY_TAB: u8 = 0x2B;
+pub const KEY_SPACE: u8 = 0x2C;
+
+const LEFT_CTRL: u8 = 0xE0;
+const LEFT_SHIFT: u8 = 0xE1;
+const RIGHT_SHIFT: u8 = 0xE5;
+const RIGHT_ALT: u8 = 0xE6;
+const RIGHT_GUI: u8 = 0xE7;
+
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum Modifier {
+    None,
+    Shift,
+    AltGr,
+}
+
+pub struct KeyboardLayout {
+    pub name: &'static str,
+    //QWERTZ layouts swap the usage ids of Y and Z
+    swap_yz: bool,
+    shifted_digits: &'static str,
+    symbols: &'static [(char, u8, Modifier)],
+}
+
+pub const US: KeyboardLayout = KeyboardLayout {
+    name: "us",
+    swap_yz: false,
+    shifted_digits: "!@#$%^&*()",
+    symbols: &[
+        ('-', 0x2D, Modifier::None), ('_', 0x2D, Modifier::Shift),
+        ('=', 0x2E, Modifier::None), ('+', 0x2E, Modifier::Shift),
+        ('[', 0x2F, Modifier::None), ('{', 0x2F, Modifier::Shift),
+        (']', 0x30, Modifier::None), ('}', 0x30, Modifier::Shift),
+        ('\\', 0x31, Modifier::None), ('|', 0x31, Modifier::Shift),
+        (';', 0x33, Modifier::None), (':', 0x33, Modifier::Shift),
+        ('\'', 0x34, Modifier::None), ('"', 0x34, Modifier::Shift),
+        ('`', 0x35, Modifier::None), ('~', 0x35, Modifier::Shift),
+        (',', 0x36, Modifier::None), ('<', 0x36, Modifier::Shift),
+        ('.', 0x37, Modifier::None), ('>', 0x37, Modifier::Shift),
+        ('/', 0x38, Modifier::None), ('?', 0x38, Modifier::Shift),
+    ],
+};
+
+pub const UK: KeyboardLayout = KeyboardLayout {
+    name: "uk",
+    swap_yz: false,
+    shifted_digits: "!\"£$%^&*()",
+    symbols: &[
+        ('-', 0x2D, Modifier::None), ('_', 0x2D, Modifier::Shift),
+        ('=', 0x2E, Modifier::None), ('+', 0x2E, Modifier::Shift),
+        ('[', 0x2F, Modifier::None), ('{', 0x2F, Modifier::Shift),
+        (']', 0x30, Modifier::None), ('}', 0x30, Modifier::Shift),
+        ('#', 0x32, Modifier::None), ('~', 0x32, Modifier::Shift),
+        (';', 0x33, Modifier::None), (':', 0x33, Modifier::Shift),
+        ('\'', 0x34, Modifier::None), ('@', 0x34, Modifier::Shift),
+        ('`', 0x35, Modifier::None), ('¬', 0x35, Modifier::Shift),
+        (',', 0x36, Modifier::None), ('<', 0x36, Modifier::Shift),
+        ('.', 0x37, Modifier::None), ('>', 0x37, Modifier::Shift),
+        ('/', 0x38, Modifier::None), ('?', 0x38, Modifier::Shift),
+        ('\\', 0x64, Modifier::None), ('|', 0x64, Modifier::Shift),
+    ],
+};
+
+pub const DE: KeyboardLayout = KeyboardLayout {
+    name: "de",
+    swap_yz: true,
+    shifted_digits: "!\"§$%&/()=",
+    symbols: &[
+        ('ß', 0x2D, Modifier::None), ('?', 0x2D, Modifier::Shift), ('\\', 0x2D, Modifier::AltGr),
+        ('ü', 0x2F, Modifier::None), ('Ü', 0x2F, Modifier::Shift),
+        ('+', 0x30, Modifier::None), ('*', 0x30, Modifier::Shift), ('~', 0x30, Modifier::AltGr),
+        ('#', 0x32, Modifier::None), ('\'', 0x32, Modifier::Shift),
+        ('ö', 0x33, Modifier::None), ('Ö', 0x33, Modifier::Shift),
+        ('ä', 0x34, Modifier::None), ('Ä', 0x34, Modifier::Shift),
+        (',', 0x36, Modifier::None), (';', 0x36, Modifier::Shift),
+        ('.', 0x37, Modifier::None), (':', 0x37, Modifier::Shift),
+        ('-', 0x38, Modifier::None), ('_', 0x38, Modifier::Shift),
+        ('<', 0x64, Modifier::None), ('>', 0x64, Modifier::Shift), ('|', 0x64, Modifier::AltGr),
+        ('@', 0x14, Modifier::AltGr), ('€', 0x08, Modifier::AltGr),
+        ('{', 0x24, Modifier::AltGr), ('[', 0x25, Modifier::AltGr),
+        (']', 0x26, Modifier::AltGr), ('}', 0x27, Modifier::AltGr),
+    ],
+};
+
+pub const LAYOUTS: [&KeyboardLayout; 3] = [&US, &UK, &DE];
+
+pub fn layout_by_name(name: &str) -> Option<&'static KeyboardLayout> {
+    LAYOUTS.iter().find(|layout| layout.name.eq_ignore_ascii_case(name)).copied()
+}
+
+impl KeyboardLayout {
+    pub fn char_to_key(&self, c: char) -> Option<(u8, Modifier)> {
+        match c {
+            ' ' => return Some((KEY_SPACE, Modifier::None)),
+            '\n' => return Some((KEY_ENTER, Modifier::None)),
+            '\t' => return Some((KEY_TAB, Modifier::None)),
+            _ => ()
+        }
+
+        if let Some((_, code, modifier)) = self.symbols.iter().find(|(symbol, _, _)| *symbol == c) {
+            return Some((*code, *modifier));
+        }
+
+        if c.is_ascii_alphabetic() {
+            let modifier = if c.is_ascii_uppercase() { Modifier::Shift } else { Modifier::None };
+            return Some((self.letter_code(c.to_ascii_lowercase()), modifier));
+        }
+
+        if c.is_ascii_digit() {
+            return Some((Self::digit_code(c), Modifier::None));
+        }
+
+        if let Some(index) = self.shifted_digits.chars().position(|digit| digit == c) {
+            return Some((KEY_1 + index as u8, Modifier::Shift));
+        }
+
+        None
+    }
+
+    pub fn key_to_char(&self, code: u8, modifier: Modifier) -> Option<char> {
+        match code {
+            KEY_SPACE => return Some(' '),
+            KEY_ENTER => return Some('\n'),
+            KEY_TAB => return Some('\t'),
+            _ => ()
+        }
+
+        if let Some((symbol, _, _)) = self.symbols.iter().find(|(_, key, m)| *key == code && *m == modifier) {
+            return Some(*symbol);
+        }
+
+        match (code, modifier) {
+            (KEY_A..=0x1D, Modifier::None) => Some(self.code_letter(code)),
+            (KEY_A..=0x1D, Modifier::Shift) => Some(self.code_letter(code).to_ascii_uppercase()),
+            (KEY_1..=KEY_0, Modifier::None) => Some(if code == KEY_0 { '0' } else { (b'1' + code - KEY_1) as char }),
+            (KEY_1..=KEY_0, Modifier::Shift) => self.shifted_digits.chars().nth((code - KEY_1) as usize),
+            _ => None
+        }
+    }
+
+    fn letter_code(&self, c: char) -> u8 {
+        let c = match c {
+            'y' if self.swap_yz => 'z',
+            'z' if self.swap_yz => 'y',
+            c => c
+        };
+        KEY_A + (c as u8 - b'a')
+    }
+
+    fn code_letter(&self, code: u8) -> char {
+        let c = (b'a' + code - KEY_A) as char;
+        match c {
+            'y' if self.swap_yz => 'z',
+            'z' if self.swap_yz => 'y',
+            c => c
+        }
+    }
+
+    fn digit_code(c: char) -> u8 {
+        if c == '0' { KEY_0 } else { KEY_1 + (c as u8 - b'1') }
+    }
+}
+
+pub fn key_code(name: &str) -> Option<u8> {
+    hid_string_to_code(&name.to_string())
+}
+
+pub fn key_name(code: u8) -> Option<&'static str> {
+    HID_KEY_STRINGS.iter().copied().find(|name| key_code(name) == Some(code))
+}
+
+pub fn mouse_name(code: u8) -> Option<&'static str> {
+    MOUSE_BUTTON_STRINGS.iter().copied().find(|name| mouse_string_to_code(&name.to_string()) == Some(code))
+}
+
+pub fn shift_code() -> u8 {
+    key_code("SHIFT").or_else(|| key_code("LEFT_SHIFT")).unwrap_or(LEFT_SHIFT)
+}
+
+pub fn alt_gr_code() -> u8 {
+    key_code("RIGHT_ALT").unwrap_or(RIGHT_ALT)
+}
+
+pub fn is_modifier(code: u8) -> bool {
+    if (LEFT_CTRL..=RIGHT_GUI).contains(&code) {
+        return true;
+    }
+
+    match key_name(code) {
+        Some(name) => ["CTRL", "SHIFT", "ALT", "GUI"].iter().any(|modifier| name.ends_with(modifier)),
+        None => false
+    }
+}
+
+pub fn is_shift(code: u8) -> bool {
+    code == LEFT_SHIFT || code == RIGHT_SHIFT || code == shift_code()
+}
@@ -7,5 +7,31 @@ pub mod compiler;
 pub mod scanner;
 pub mod token;
 pub mod parser;
+pub mod diagnostic;
+pub mod layout;
+pub mod trace;
+pub mod simulator;
+pub mod golden;
+pub mod estimate;
+pub mod disassembler;
+pub mod optimizer;
+pub mod fmt;
+pub mod repl;
+pub mod natives;
+pub mod types;
+pub mod lint;
+pub mod flow;
+pub mod params;
+pub mod modules;
+pub mod stdlib;
+pub mod shake;
+pub mod ducky;
+pub mod export;
+pub mod backend;
+pub mod typing;
+pub mod media;
+#[cfg(test)]
+pub mod test_support;
 
 type ScannerRef<'a> = Rc<RefCell<Scanner<'a>>>;
+pub mod mouse;
@@ -0,0 +1,105 @@
+use std::collections::HashMap;
+use std::fmt;
+use crate::scanner::Scanner;
+use crate::token::TokenType;
+
+pub const UNUSED: &str = "unused";
+pub const SHADOWING: &str = "shadowing";
+pub const REDECLARATION: &str = "redeclaration";
+pub const UNREACHABLE: &str = "unreachable";
+pub const MISSING_RETURN: &str = "missing_return";
+
+//`// sanc:allow` silences every warning, `// sanc:allow(unused, shadowing)` only the listed ones
+const PRAGMA: &str = "sanc:allow";
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum DeclarationKind {
+    Variable,
+    Parameter,
+    Function,
+    Constant,
+}
+
+impl fmt::Display for DeclarationKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DeclarationKind::Variable => write!(f, "variable"),
+            DeclarationKind::Parameter => write!(f, "parameter"),
+            DeclarationKind::Function => write!(f, "function"),
+            DeclarationKind::Constant => write!(f, "constant"),
+        }
+    }
+}
+
+//lints allowed by pragma comments per line, `*` stands for every lint
+#[derive(Clone, Debug, Default)]
+pub struct AllowedLints {
+    lines: HashMap<usize, Vec<String>>,
+}
+
+impl AllowedLints {
+    pub fn from_source(source: &str) -> AllowedLints {
+        let mut allowed = AllowedLints::default();
+        let mut scanner = Scanner::with_trivia(source);
+        //line of the last token that is not trivia
+        let mut code_line = 0;
+
+        loop {
+            let token = scanner.scan_token();
+            match token.token_type {
+                TokenType::EOF => return allowed,
+                TokenType::Comment => {
+                    let comment = token.get_token_string(source);
+                    if let Some(lints) = AllowedLints::parse_pragma(comment.trim_start_matches('/').trim()) {
+                        //a pragma covers its own line and, when it stands on a line of its own, the next one
+                        allowed.lines.entry(token.line).or_default().extend(lints.clone());
+                        if code_line != token.line {
+                            allowed.lines.entry(token.line + 1).or_default().extend(lints);
+                        }
+                    }
+                }
+                TokenType::Whitespace | TokenType::Newline => (),
+                _ => code_line = token.line,
+            }
+        }
+    }
+
+    fn parse_pragma(comment: &str) -> Option<Vec<String>> {
+        let rest = comment.strip_prefix(PRAGMA)?.trim();
+        if rest.is_empty() {
+            return Some(vec![String::from("*")]);
+        }
+
+        let lints = rest.strip_prefix('(')?.strip_suffix(')')?;
+        Some(lints.split(',').map(|lint| lint.trim().to_string()).filter(|lint| !lint.is_empty()).collect())
+    }
+
+    pub fn is_allowed(&self, line: usize, lint: &str) -> bool {
+        match self.lines.get(&line) {
+            Some(lints) => lints.iter().any(|allowed| allowed == lint || allowed == "*"),
+            None => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{AllowedLints, SHADOWING, UNUSED};
+
+    #[test]
+    fn pragma_on_its_own_line_covers_the_next_line() {
+        let allowed = AllowedLints::from_source("// sanc:allow(unused)\nlet a = 1;\nlet b = 2;\n");
+        assert!(allowed.is_allowed(1, UNUSED));
+        assert!(allowed.is_allowed(2, UNUSED));
+        assert!(!allowed.is_allowed(3, UNUSED));
+        assert!(!allowed.is_allowed(2, SHADOWING));
+    }
+
+    #[test]
+    fn trailing_pragma_covers_only_its_line() {
+        let allowed = AllowedLints::from_source("let a = 1; // sanc:allow\nlet b = 2;\n");
+        assert!(allowed.is_allowed(1, UNUSED));
+        assert!(allowed.is_allowed(1, SHADOWING));
+        assert!(!allowed.is_allowed(2, UNUSED));
+    }
+}
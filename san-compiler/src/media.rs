@@ -0,0 +1,44 @@
+pub const GENERIC_DESKTOP_PAGE: u16 = 0x01;
+pub const CONSUMER_PAGE: u16 = 0x0C;
+
+//a key outside the keyboard usage page, sent through the consumer or system control report,
+//the MEDIA_ and SYSTEM_ prefixes keep the names apart from the keys of the keyboard page
+pub struct MediaKey {
+    pub name: &'static str,
+    pub page: u16,
+    pub usage: u16,
+}
+
+pub static MEDIA_KEYS: [MediaKey; 12] = [
+    MediaKey { name: "MEDIA_PLAY_PAUSE", page: CONSUMER_PAGE, usage: 0xCD },
+    MediaKey { name: "MEDIA_NEXT_TRACK", page: CONSUMER_PAGE, usage: 0xB5 },
+    MediaKey { name: "MEDIA_PREVIOUS_TRACK", page: CONSUMER_PAGE, usage: 0xB6 },
+    MediaKey { name: "MEDIA_STOP", page: CONSUMER_PAGE, usage: 0xB7 },
+    MediaKey { name: "MEDIA_MUTE", page: CONSUMER_PAGE, usage: 0xE2 },
+    MediaKey { name: "MEDIA_VOLUME_UP", page: CONSUMER_PAGE, usage: 0xE9 },
+    MediaKey { name: "MEDIA_VOLUME_DOWN", page: CONSUMER_PAGE, usage: 0xEA },
+    MediaKey { name: "MEDIA_BRIGHTNESS_UP", page: CONSUMER_PAGE, usage: 0x6F },
+    MediaKey { name: "MEDIA_BRIGHTNESS_DOWN", page: CONSUMER_PAGE, usage: 0x70 },
+    MediaKey { name: "SYSTEM_POWER", page: GENERIC_DESKTOP_PAGE, usage: 0x81 },
+    MediaKey { name: "SYSTEM_SLEEP", page: GENERIC_DESKTOP_PAGE, usage: 0x82 },
+    MediaKey { name: "SYSTEM_WAKE", page: GENERIC_DESKTOP_PAGE, usage: 0x83 },
+];
+
+impl MediaKey {
+    //SanVM values have no variant for media keys, so they are numbers holding the usage page above the usage id
+    pub fn code(&self) -> u32 {
+        ((self.page as u32) << 16) | self.usage as u32
+    }
+
+    pub fn is_system(&self) -> bool {
+        self.page == GENERIC_DESKTOP_PAGE
+    }
+}
+
+pub fn media_key(name: &str) -> Option<&'static MediaKey> {
+    MEDIA_KEYS.iter().find(|key| key.name == name)
+}
+
+pub fn media_key_by_code(code: u32) -> Option<&'static MediaKey> {
+    MEDIA_KEYS.iter().find(|key| key.code() == code)
+}
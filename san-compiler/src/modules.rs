@@ -0,0 +1,184 @@
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use crate::compiler::Constant;
+use crate::stdlib::{std_path, std_source};
+use crate::types::Type;
+
+//file id of the source handed to the compiler, imported files are numbered from 1
+pub const ROOT_FILE: usize = 0;
+
+//a compiled module, its globals are stored as `prefix.name` next to the globals of the payload
+#[derive(Clone)]
+pub struct Module {
+    pub prefix: String,
+    pub exports: HashMap<String, Type>,
+    pub constants: Vec<Constant>,
+}
+
+#[derive(Clone)]
+pub struct SourceFile {
+    pub path: PathBuf,
+    pub source: String,
+}
+
+//shared by every compiler of one payload, so each file is compiled and linked only once
+#[derive(Default)]
+pub struct ModuleLoader {
+    search_paths: Vec<PathBuf>,
+    files: Vec<SourceFile>,
+    modules: HashMap<PathBuf, Module>,
+    prefixes: Vec<String>,
+    //files that are being compiled, outermost first
+    loading: Vec<PathBuf>,
+}
+
+impl ModuleLoader {
+    pub fn new() -> ModuleLoader {
+        ModuleLoader::default()
+    }
+
+    pub fn add_search_path(&mut self, path: PathBuf) {
+        self.search_paths.push(path);
+    }
+
+    //`std/` imports are embedded, others are looked up next to the importing file first, then in the search paths in order
+    pub fn resolve(&self, import: &str, importer: Option<&Path>) -> Option<PathBuf> {
+        if let Some(path) = std_path(import) {
+            return Some(path);
+        }
+        let base = importer.and_then(Path::parent).map(Path::to_path_buf).unwrap_or_default();
+        std::iter::once(base)
+            .chain(self.search_paths.iter().cloned())
+            .map(|directory| directory.join(import))
+            .find(|path| path.is_file())
+            .map(|path| fs::canonicalize(&path).unwrap_or(path))
+    }
+
+    pub fn read(&self, path: &Path) -> io::Result<String> {
+        match std_source(path) {
+            Some(source) => Ok(source.to_string()),
+            None => fs::read_to_string(path),
+        }
+    }
+
+    pub fn add_file(&mut self, path: PathBuf, source: String) -> usize {
+        self.files.push(SourceFile { path, source });
+        self.files.len()
+    }
+
+    pub fn file(&self, file: usize) -> Option<&SourceFile> {
+        if file == ROOT_FILE { None } else { self.files.get(file - 1) }
+    }
+
+    pub fn module(&self, path: &Path) -> Option<&Module> {
+        self.modules.get(path)
+    }
+
+    pub fn add_module(&mut self, path: PathBuf, module: Module) {
+        self.modules.insert(path, module);
+    }
+
+    //modules named like another one get a number, `.` can't appear in a name so prefixes never clash with globals
+    pub fn reserve_prefix(&mut self, path: &Path) -> String {
+        let stem = path.file_stem().map_or(String::from("module"), |stem| stem.to_string_lossy().to_string());
+        let mut prefix = stem.clone();
+        let mut suffix = 1;
+        while self.prefixes.contains(&prefix) {
+            suffix += 1;
+            prefix = format!("{}{}", stem, suffix);
+        }
+        self.prefixes.push(prefix.clone());
+        prefix
+    }
+
+    pub fn begin_loading(&mut self, path: PathBuf) {
+        self.loading.push(path);
+    }
+
+    pub fn end_loading(&mut self) {
+        self.loading.pop();
+    }
+
+    //the chain of imports that leads back to the path, if it is still being compiled
+    pub fn cycle(&self, path: &Path) -> Option<String> {
+        let start = self.loading.iter().position(|loading| loading == path)?;
+        let mut names: Vec<String> = self.loading[start..].iter().map(|loading| ModuleLoader::file_name(loading)).collect();
+        names.push(ModuleLoader::file_name(path));
+        Some(names.join(" -> "))
+    }
+
+    fn file_name(path: &Path) -> String {
+        path.file_name().map_or(String::new(), |name| name.to_string_lossy().to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::path::{Path, PathBuf};
+    use san_common::value::FunctionData;
+    use crate::test_support::{compile_at, printed};
+    use super::{ModuleLoader, ROOT_FILE};
+
+    //writes the files into a fresh directory and compiles the first one
+    fn compile_files(name: &str, files: &[(&str, &str)]) -> Option<FunctionData> {
+        let directory = std::env::temp_dir().join(format!("sanc-modules-{}-{}", name, std::process::id()));
+        fs::create_dir_all(&directory).unwrap();
+        for (file, source) in files {
+            fs::write(directory.join(file), source).unwrap();
+        }
+
+        let (root, source) = files[0];
+        let function = compile_at(source, &directory.join(root));
+        fs::remove_dir_all(&directory).unwrap();
+        function
+    }
+
+    #[test]
+    fn cycles_list_the_import_chain() {
+        let mut loader = ModuleLoader::new();
+        loader.begin_loading(PathBuf::from("/p/a.san"));
+        loader.begin_loading(PathBuf::from("/p/b.san"));
+        assert_eq!(loader.cycle(Path::new("/p/a.san")), Some(String::from("a.san -> b.san -> a.san")));
+        assert_eq!(loader.cycle(Path::new("/p/c.san")), None);
+        loader.end_loading();
+        assert_eq!(loader.cycle(Path::new("/p/b.san")), None);
+    }
+
+    #[test]
+    fn prefixes_of_files_with_the_same_name_are_numbered() {
+        let mut loader = ModuleLoader::new();
+        assert_eq!(loader.reserve_prefix(Path::new("/p/launch.san")), "launch");
+        assert_eq!(loader.reserve_prefix(Path::new("/q/launch.san")), "launch2");
+    }
+
+    #[test]
+    fn imported_files_are_numbered_from_one() {
+        let mut loader = ModuleLoader::new();
+        let file = loader.add_file(PathBuf::from("/p/a.san"), String::from("print 1;"));
+        assert_eq!(file, 1);
+        assert_eq!(loader.file(file).unwrap().path, PathBuf::from("/p/a.san"));
+        assert!(loader.file(ROOT_FILE).is_none());
+    }
+
+    #[test]
+    fn imported_functions_are_called_through_their_namespace() {
+        let function = compile_files("namespace", &[
+            ("main.san", "import \"greet.san\" as greet;\ngreet.hello();\n"),
+            ("greet.san", "fn hello() {\n    print \"hi\";\n}\n"),
+        ]).expect("payload does not compile");
+
+        assert_eq!(printed(function), vec!["hi"]);
+    }
+
+    #[test]
+    fn import_cycles_fail_to_compile() {
+        let function = compile_files("cycle", &[
+            ("a.san", "import \"b.san\" as b;\n"),
+            ("b.san", "import \"a.san\" as a;\n"),
+        ]);
+        assert!(function.is_none());
+    }
+}
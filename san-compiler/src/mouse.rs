@@ -0,0 +1,4 @@
+//logical maximum of the absolute digitizer axes, (0, 0) is the top left corner of the screen
+pub const DIGITIZER_MAX: f64 = 32767.0;
+//a scroll report holds a signed byte for each wheel
+pub const SCROLL_MAX: f64 = 127.0;
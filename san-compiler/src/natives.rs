@@ -0,0 +1,152 @@
+use std::collections::HashSet;
+use std::sync::Arc;
+use san_common::chunk::OpCode;
+use san_common::value::{FunctionData, Value};
+use crate::mouse::{DIGITIZER_MAX, SCROLL_MAX};
+use crate::optimizer::decode;
+use crate::types::{Signature, Type};
+
+//typed signatures of the natives, shared by the compiler, simulator, estimator and language server,
+//calls still go through a global lookup by name since SanVM has no native index table yet
+pub struct Native {
+    pub name: &'static str,
+    pub params: &'static [Type],
+    pub returns: Type,
+    //trailing params that can be left out, the compiler passes nil for them
+    pub optional: usize,
+    //false for natives only the simulator and the Arduino and CircuitPython backends implement so far,
+    //SanVM binaries can't call them
+    pub device: bool,
+}
+
+pub static NATIVES: [Native; 18] = [
+    Native { name: "inject_keys", params: &[Type::Keys], returns: Type::Nil, optional: 0, device: true },
+    //without delay and hold the keys are typed with the typing profile
+    Native { name: "inject_sequence", params: &[Type::Keys, Type::Number, Type::Number], returns: Type::Nil, optional: 2, device: true },
+    Native { name: "string_to_keys", params: &[Type::String], returns: Type::Keys, optional: 0, device: true },
+    Native { name: "sleep", params: &[Type::Number], returns: Type::Nil, optional: 0, device: true },
+    Native { name: "mouse_move", params: &[Type::Number, Type::Number], returns: Type::Nil, optional: 0, device: true },
+    Native { name: "mouse_hold", params: &[Type::MouseButton], returns: Type::Nil, optional: 0, device: true },
+    Native { name: "mouse_up", params: &[], returns: Type::Nil, optional: 0, device: true },
+    //only reachable from assert statements, which are compiled into test blocks
+    Native { name: "__assert_fail", params: &[Type::String], returns: Type::Nil, optional: 0, device: false },
+    Native { name: "type_text", params: &[Type::String], returns: Type::Nil, optional: 0, device: false },
+    //speed, jitter and seed, emitted for `typing { ... }` and the [typing] table of config.toml
+    Native { name: "__set_typing", params: &[Type::Number, Type::Number, Type::Number], returns: Type::Nil, optional: 0, device: false },
+    //passed for the delay and hold left out of inject_sequence, nil tells the natives to type with the profile
+    Native { name: "__typing_default", params: &[], returns: Type::Nil, optional: 0, device: false },
+    //keys stay pressed from key_down until key_up or release_all, and are part of every key report in between
+    Native { name: "key_down", params: &[Type::Keys], returns: Type::Nil, optional: 0, device: false },
+    Native { name: "key_up", params: &[Type::Keys], returns: Type::Nil, optional: 0, device: false },
+    Native { name: "release_all", params: &[], returns: Type::Nil, optional: 0, device: false },
+    //a media key, how many times to press it and the delay after every press, which defaults to the typing profile
+    Native { name: "inject_media", params: &[Type::MediaKey, Type::Number, Type::Number], returns: Type::Nil, optional: 2, device: false },
+    //vertical and horizontal wheel, positive values scroll up and right
+    Native { name: "mouse_scroll", params: &[Type::Number, Type::Number], returns: Type::Nil, optional: 1, device: false },
+    Native { name: "mouse_click", params: &[Type::MouseButton, Type::Number], returns: Type::Nil, optional: 1, device: false },
+    //absolute digitizer coordinates from 0 to 32767 on both axes
+    Native { name: "mouse_move_to", params: &[Type::Number, Type::Number], returns: Type::Nil, optional: 0, device: false },
+];
+
+impl Native {
+    pub fn arity(&self) -> usize {
+        self.params.len()
+    }
+
+    pub fn signature(&self) -> Signature {
+        Signature {
+            name: self.name.to_string(),
+            params: self.params.to_vec(),
+            returns: self.returns.clone(),
+            optional: self.optional,
+        }
+    }
+
+    pub fn function_type(&self) -> Type {
+        Type::Function(Some(Arc::new(self.signature())))
+    }
+}
+
+pub fn native_by_name(name: &str) -> Option<&'static Native> {
+    NATIVES.iter().find(|native| native.name == name)
+}
+
+//lines and names of the natives a SanVM binary would call without the device implementing them,
+//the payload would stop there with an undefined global
+pub fn missing_on_device(function: &FunctionData) -> Vec<(usize, &'static str)> {
+    let mut defined = HashSet::new();
+    let mut calls = vec![];
+    collect_globals(function, &mut defined, &mut calls);
+    calls.retain(|(_, name)| !defined.contains(*name));
+    calls.sort();
+    calls.dedup();
+    calls
+}
+
+fn collect_globals(function: &FunctionData, defined: &mut HashSet<String>, calls: &mut Vec<(usize, &'static str)>) {
+    for instruction in decode(&function.chunk) {
+        match (instruction.op, &instruction.constant) {
+            (OpCode::OpDefineGlobal(_), Some(Value::ValString(name))) => {
+                defined.insert(name.clone());
+            }
+            (OpCode::OpGetGlobal(_), Some(Value::ValString(name))) => {
+                if let Some(native) = native_by_name(name).filter(|native| !native.device) {
+                    calls.push((instruction.line, native.name));
+                }
+            }
+            (_, Some(Value::ValFunction(nested))) => collect_globals(nested, defined, calls),
+            _ => {}
+        }
+    }
+}
+
+//checks the arguments of a native call that fold to constants, the others are None and are checked by the
+//native at run time, returns the index of the first argument out of range with a message
+pub fn check_constant_arguments(name: &str, arguments: &[Option<Value>]) -> Option<(usize, String)> {
+    let ranges = match name {
+        "mouse_scroll" => vec![(0, -SCROLL_MAX, SCROLL_MAX, "Scroll amounts"), (1, -SCROLL_MAX, SCROLL_MAX, "Scroll amounts")],
+        "mouse_click" => vec![(1, 1.0, f64::MAX, "Click counts")],
+        "mouse_move_to" => vec![(0, 0.0, DIGITIZER_MAX, "Absolute coordinates"), (1, 0.0, DIGITIZER_MAX, "Absolute coordinates")],
+        _ => vec![],
+    };
+
+    ranges.into_iter().find_map(|(index, min, max, what)| {
+        let value = match arguments.get(index) {
+            Some(Some(Value::ValNumber(value))) => *value,
+            _ => return None,
+        };
+        if value.fract() == 0.0 && value >= min && value <= max {
+            return None;
+        }
+        let range = if max == f64::MAX { format!("of at least {}", min) } else { format!("between {} and {}", min, max) };
+        Some((index, format!("{} must be whole numbers {}, found {}", what, range, value)))
+    })
+}
+
+//the native a misspelled name most likely refers to, if it is at most two edits away
+pub fn suggest_native(name: &str) -> Option<&'static str> {
+    NATIVES
+        .iter()
+        .filter(|native| !native.name.starts_with("__"))
+        .map(|native| (edit_distance(name, native.name), native.name))
+        .filter(|(distance, _)| *distance > 0 && *distance <= 2)
+        .min_by_key(|(distance, _)| *distance)
+        .map(|(_, native)| native)
+}
+
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut row: Vec<usize> = (0..=b.len()).collect();
+
+    for (i, ca) in a.chars().enumerate() {
+        let mut diagonal = row[0];
+        row[0] = i + 1;
+        for (j, cb) in b.iter().enumerate() {
+            let substitution = diagonal + if ca == *cb { 0 } else { 1 };
+            diagonal = row[j + 1];
+            row[j + 1] = substitution.min(row[j] + 1).min(row[j + 1] + 1);
+        }
+    }
+
+    row[b.len()]
+}
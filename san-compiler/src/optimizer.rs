@@ -0,0 +1,243 @@
+use std::collections::HashSet;
+use san_common::chunk::{Chunk, OpCode};
+use san_common::value::{FunctionData, Value};
+use crate::flow::{constant_condition, reachable};
+use crate::simulator::{binary_op, unary_op, SimValue};
+
+//a decoded instruction that remembers where it came from, so passes can drop
+//or merge instructions and still relink the jumps afterwards
+#[derive(Clone)]
+pub struct Instruction {
+    //offset in the original chunk
+    pub id: usize,
+    pub op: OpCode,
+    pub line: usize,
+    //resolved operand of the opcodes that index the constant table
+    pub constant: Option<Value>,
+    //original offset a jump lands on
+    pub target: Option<usize>,
+}
+
+pub fn optimize(function: &FunctionData) -> FunctionData {
+    let mut instructions = decode(&function.chunk);
+    for instruction in instructions.iter_mut() {
+        if let Some(Value::ValFunction(nested)) = &instruction.constant {
+            instruction.constant = Some(Value::ValFunction(optimize(nested)));
+        }
+    }
+
+    let instructions = remove_unreachable(fold_constants(instructions));
+    let mut optimized = function.clone();
+    optimized.chunk = encode(&instructions);
+    optimized
+}
+
+pub fn decode(chunk: &Chunk) -> Vec<Instruction> {
+    (0..chunk.len()).map(|id| {
+        let op = chunk.get_code(id).clone();
+        Instruction {
+            id,
+            op,
+            line: chunk.get_line(id),
+            constant: constant_operand(op).map(|index| chunk.get_constant(index).clone()),
+            target: jump_target(op, id),
+        }
+    }).collect()
+}
+
+pub fn encode(instructions: &[Instruction]) -> Chunk {
+    let mut chunk = Chunk::new();
+    append(&mut chunk, instructions);
+    chunk
+}
+
+//jumps are relative, so instructions can be appended to code that is already in the chunk
+pub fn append(chunk: &mut Chunk, instructions: &[Instruction]) {
+    for (offset, instruction) in instructions.iter().enumerate() {
+        let target = instruction.target.map(|target| instructions.partition_point(|other| other.id < target));
+        let op = match (instruction.op, target) {
+            (OpCode::OpJump(_), Some(target)) => OpCode::OpJump(target - offset - 1),
+            (OpCode::OpJumpIfFalse(_), Some(target)) => OpCode::OpJumpIfFalse(target - offset - 1),
+            (OpCode::OpJumpIfTrue(_), Some(target)) => OpCode::OpJumpIfTrue(target - offset - 1),
+            (OpCode::OpLoop(_), Some(target)) => OpCode::OpLoop(offset + 1 - target),
+            (op, _) => match &instruction.constant {
+                Some(value) => with_constant_operand(op, add_constant(chunk, value.clone())),
+                None => op,
+            }
+        };
+        chunk.write_chunk(op, instruction.line);
+    }
+}
+
+pub fn constant_operand(op: OpCode) -> Option<usize> {
+    match op {
+        OpCode::OpConstant(index) | OpCode::OpDefineGlobal(index) | OpCode::OpGetGlobal(index) | OpCode::OpSetGlobal(index) => Some(index),
+        _ => None
+    }
+}
+
+fn with_constant_operand(op: OpCode, index: usize) -> OpCode {
+    match op {
+        OpCode::OpConstant(_) => OpCode::OpConstant(index),
+        OpCode::OpDefineGlobal(_) => OpCode::OpDefineGlobal(index),
+        OpCode::OpGetGlobal(_) => OpCode::OpGetGlobal(index),
+        OpCode::OpSetGlobal(_) => OpCode::OpSetGlobal(index),
+        op => op
+    }
+}
+
+pub fn jump_target(op: OpCode, offset: usize) -> Option<usize> {
+    match op {
+        OpCode::OpJump(jump) | OpCode::OpJumpIfFalse(jump) | OpCode::OpJumpIfTrue(jump) => Some(offset + 1 + jump),
+        OpCode::OpLoop(jump) => Some(offset + 1 - jump),
+        _ => None
+    }
+}
+
+fn add_constant(chunk: &mut Chunk, value: Value) -> usize {
+    let offset = chunk.has_constant(&value);
+    if offset == -1 {
+        return chunk.add_constant(value);
+    }
+    offset as usize
+}
+
+pub fn constant_value(instruction: &Instruction) -> Option<SimValue> {
+    match instruction.op {
+        OpCode::OpNil => Some(SimValue::Nil),
+        OpCode::OpTrue => Some(SimValue::Bool(true)),
+        OpCode::OpFalse => Some(SimValue::Bool(false)),
+        OpCode::OpConstant(_) => SimValue::from_constant(instruction.constant.as_ref()?),
+        _ => None
+    }
+}
+
+fn constant_instruction(id: usize, line: usize, value: SimValue) -> Option<Instruction> {
+    let (op, constant) = match value {
+        SimValue::Nil => (OpCode::OpNil, None),
+        SimValue::Bool(true) => (OpCode::OpTrue, None),
+        SimValue::Bool(false) => (OpCode::OpFalse, None),
+        value => (OpCode::OpConstant(0), Some(value.to_constant()?)),
+    };
+    Some(Instruction { id, op, line, constant, target: None })
+}
+
+//runs after folding, so conditions that fold to a constant drop the branch they never take,
+//their jump goes with it or becomes unconditional, so it never points at a dropped instruction
+fn remove_unreachable(instructions: Vec<Instruction>) -> Vec<Instruction> {
+    let targets: HashSet<usize> = instructions.iter().filter_map(|instruction| instruction.target).collect();
+    let taken: Vec<Option<bool>> = (0..instructions.len()).map(|index| match instructions[index].op {
+        OpCode::OpJumpIfFalse(_) | OpCode::OpJumpIfTrue(_) => {
+            let jumps_if_falsey = matches!(instructions[index].op, OpCode::OpJumpIfFalse(_));
+            constant_condition(&instructions, index, &targets).map(|falsey| falsey == jumps_if_falsey)
+        }
+        _ => None
+    }).collect();
+    let reachable = reachable(&instructions);
+
+    instructions
+        .into_iter()
+        .zip(reachable)
+        .zip(taken)
+        .filter_map(|((instruction, reachable), taken)| match (reachable, taken) {
+            (false, _) | (true, Some(false)) => None,
+            //the condition stays on the stack for the OpPop on either side of the jump
+            (true, Some(true)) => Some(Instruction { op: OpCode::OpJump(0), ..instruction }),
+            (true, None) => Some(instruction),
+        })
+        .collect()
+}
+
+//replaces operations on constants with their result, as long as no jump lands in the middle of them
+pub fn fold_constants(instructions: Vec<Instruction>) -> Vec<Instruction> {
+    let targets: HashSet<usize> = instructions.iter().filter_map(|instruction| instruction.target).collect();
+    let mut output: Vec<Instruction> = Vec::with_capacity(instructions.len());
+
+    for instruction in instructions {
+        let folded = match instruction.op {
+            OpCode::OpAdd | OpCode::OpSubtract | OpCode::OpMultiply | OpCode::OpDivide | OpCode::OpPipe
+            | OpCode::OpEqual | OpCode::OpGreater | OpCode::OpLess if output.len() >= 2 => {
+                let a = &output[output.len() - 2];
+                let b = &output[output.len() - 1];
+                if targets.contains(&b.id) || targets.contains(&instruction.id) {
+                    None
+                } else {
+                    match (constant_value(a), constant_value(b)) {
+                        (Some(a_value), Some(b_value)) => binary_op(instruction.op, a_value, b_value).ok()
+                            .and_then(|value| constant_instruction(a.id, instruction.line, value))
+                            .map(|folded| (2, folded)),
+                        _ => None
+                    }
+                }
+            }
+            OpCode::OpNot | OpCode::OpNegate if !output.is_empty() && !targets.contains(&instruction.id) => {
+                let a = &output[output.len() - 1];
+                constant_value(a)
+                    .and_then(|value| unary_op(instruction.op, value).ok())
+                    .and_then(|value| constant_instruction(a.id, instruction.line, value))
+                    .map(|folded| (1, folded))
+            }
+            _ => None
+        };
+
+        match folded {
+            Some((operands, folded)) => {
+                output.truncate(output.len() - operands);
+                output.push(folded);
+            }
+            None => output.push(instruction),
+        }
+    }
+
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use san_common::value::{FunctionData, Value};
+    use crate::test_support::compile;
+    use super::{jump_target, optimize};
+
+    fn optimized(source: &str) -> FunctionData {
+        optimize(&compile(source).expect("source does not compile"))
+    }
+
+    fn ops(function: &FunctionData) -> Vec<String> {
+        assert_jumps_in_bounds(function);
+        (0..function.chunk.len()).map(|offset| format!("{:?}", function.chunk.get_code(offset))).collect()
+    }
+
+    fn assert_jumps_in_bounds(function: &FunctionData) {
+        for offset in 0..function.chunk.len() {
+            if let Some(target) = jump_target(function.chunk.get_code(offset).clone(), offset) {
+                assert!(target < function.chunk.len(), "jump at {} lands on {} past the end of the chunk", offset, target);
+            }
+        }
+    }
+
+    #[test]
+    fn constant_if_keeps_only_the_taken_branch() {
+        let function = optimized("if (false) { print 1; } else { print 2; }");
+        //the jump is always taken, so it becomes unconditional and lands right after itself
+        assert_eq!(ops(&function), vec!["OpFalse", "OpJump(0)", "OpPop", "OpConstant(0)", "OpPrint", "OpNil", "OpReturn"]);
+        assert!(matches!(function.chunk.get_constant(0), Value::ValNumber(number) if *number == 2.0));
+    }
+
+    #[test]
+    fn endless_while_drops_the_code_after_it() {
+        let function = optimized("while (true) { print 1; } print 2;");
+        //the exit jump is never taken and goes away together with the code it led to
+        assert_eq!(ops(&function), vec!["OpTrue", "OpPop", "OpConstant(0)", "OpPrint", "OpLoop(5)"]);
+    }
+
+    #[test]
+    fn jumps_stay_in_bounds_around_constant_conditions() {
+        for source in [
+            "while (true) { if (false) { print 1; } }",
+            "let a = 1;\nwhile (a < 3) { if (true) { a = a + 1; } else { print a; } }\nprint a;",
+            "print false and 1;\nprint true or 2;\nfor (let i = 0; true; i = i + 1) { print i; }",
+        ] {
+            assert_jumps_in_bounds(&optimized(source));
+        }
+    }
+}
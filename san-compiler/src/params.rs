@@ -0,0 +1,188 @@
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::Path;
+use san_common::value::{Number, Value};
+use crate::typing::TypingProfile;
+
+pub const CONFIG_FILE: &str = "config.toml";
+const PARAMS_TABLE: &str = "[params]";
+const TYPING_TABLE: &str = "[typing]";
+//`--target` is a shorthand for `-D target=<os>`, so the target can also be set in config.toml
+pub const TARGET_PARAM: &str = "target";
+pub const TARGETS: [&str; 3] = ["windows", "linux", "macos"];
+
+//compile-time parameters of a payload, read in source with `param("name", default)`
+#[derive(Clone, Debug, Default)]
+pub struct Params {
+    values: HashMap<String, Value>,
+    //the [typing] table, the script starts by setting it as its typing profile
+    pub typing: Option<TypingProfile>,
+}
+
+impl Params {
+    pub fn new() -> Params {
+        Params { values: HashMap::new(), typing: None }
+    }
+
+    pub fn target(&self) -> Option<&str> {
+        match self.values.get(TARGET_PARAM) {
+            Some(Value::ValString(target)) => Some(target.as_str()),
+            _ => None,
+        }
+    }
+
+    //the `[params]` table of the config.toml next to the payload, no config means no params
+    pub fn from_config(source_path: &Path) -> io::Result<Params> {
+        let config_path = source_path.with_file_name(CONFIG_FILE);
+        let config = match fs::read_to_string(&config_path) {
+            Ok(config) => config,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Params::new()),
+            Err(e) => return Err(e),
+        };
+
+        Params::parse_config(config.as_str())
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, format!("{}: {}", config_path.display(), e)))
+    }
+
+    //only the part of TOML the payload configs use: tables and keys with string, number or boolean values
+    pub fn parse_config(config: &str) -> Result<Params, String> {
+        let mut params = Params::new();
+        let mut table = String::new();
+
+        for (index, line) in config.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            if line.starts_with('[') {
+                table = line.replace(' ', "");
+                continue;
+            }
+            if table != PARAMS_TABLE && table != TYPING_TABLE {
+                continue;
+            }
+
+            let (name, value) = line
+                .split_once('=')
+                .ok_or_else(|| format!("line {}: expected 'name = value'", index + 1))?;
+            let value = Params::parse_toml_value(value.trim())
+                .ok_or_else(|| format!("line {}: unsupported value '{}'", index + 1, value.trim()))?;
+            let name = name.trim().trim_matches('"');
+            if table == TYPING_TABLE {
+                params.set_typing(name, &value).map_err(|e| format!("line {}: {}", index + 1, e))?;
+            } else {
+                params.define(name, value);
+            }
+        }
+
+        Ok(params)
+    }
+
+    //typing settings are milliseconds, given as a number or a string like "30ms"
+    fn set_typing(&mut self, setting: &str, value: &Value) -> Result<(), String> {
+        let number = match value {
+            Value::ValNumber(number) => Some(*number),
+            Value::ValString(text) => text.trim_end_matches("ms").trim().parse::<Number>().ok(),
+            _ => None,
+        };
+        let number = number
+            .filter(|number| *number >= 0.0)
+            .ok_or_else(|| format!("typing {} must be a non-negative number of milliseconds", setting))?;
+        self.typing.get_or_insert_with(TypingProfile::default).set(setting, number as u64)
+    }
+
+    fn parse_toml_value(text: &str) -> Option<Value> {
+        if let Some(rest) = text.strip_prefix('"') {
+            let (string, trailing) = rest.split_once('"')?;
+            let trailing = trailing.trim();
+            return if trailing.is_empty() || trailing.starts_with('#') { Some(Value::ValString(string.to_string())) } else { None };
+        }
+
+        let text = text.split('#').next().unwrap_or_default().trim();
+        match text {
+            "true" => Some(Value::ValBool(true)),
+            "false" => Some(Value::ValBool(false)),
+            _ => text.parse::<Number>().ok().map(Value::ValNumber),
+        }
+    }
+
+    //`name=value` of a -D flag, values that don't look like a number or boolean are strings
+    pub fn parse_define(define: &str) -> Result<(String, Value), String> {
+        let (name, value) = match define.split_once('=') {
+            Some((name, value)) if !name.trim().is_empty() => (name.trim(), value.trim()),
+            _ => return Err(format!("Expected 'name=value' after -D, found '{}'", define)),
+        };
+
+        let value = match value {
+            "true" => Value::ValBool(true),
+            "false" => Value::ValBool(false),
+            _ => match value.parse::<Number>() {
+                Ok(number) => Value::ValNumber(number),
+                Err(_) => Value::ValString(value.trim_matches('"').to_string()),
+            }
+        };
+        Ok((name.to_string(), value))
+    }
+
+    pub fn define(&mut self, name: &str, value: Value) {
+        self.values.insert(name.to_string(), value);
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Value> {
+        self.values.get(name)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use san_common::value::Value;
+    use crate::test_support::{compile_with, printed};
+    use super::Params;
+
+    const CONFIG: &str = "layout = \"us\"\n\n[params]\nurl = \"https://example.com\" # the page\ncount = 3\n\n[other]\nignored = \"x\"\n\n[typing]\nspeed = \"20ms\"\n";
+
+    //the printed values of a script compiled with the params
+    fn printed_with(source: &str, params: Params) -> Option<Vec<String>> {
+        compile_with(source, params).map(printed)
+    }
+
+    #[test]
+    fn config_reads_params_and_typing_tables_only() {
+        let params = Params::parse_config(CONFIG).unwrap();
+        assert!(matches!(params.get("url"), Some(Value::ValString(url)) if url == "https://example.com"));
+        assert!(matches!(params.get("count"), Some(Value::ValNumber(count)) if *count == 3.0));
+        assert!(params.get("layout").is_none());
+        assert!(params.get("ignored").is_none());
+        assert_eq!(params.typing.map(|typing| typing.speed), Some(20));
+    }
+
+    #[test]
+    fn config_errors_name_the_line() {
+        assert_eq!(Params::parse_config("[params]\nurl\n").unwrap_err(), "line 2: expected 'name = value'");
+    }
+
+    #[test]
+    fn defines_override_the_config() {
+        let mut params = Params::parse_config(CONFIG).unwrap();
+        let (name, value) = Params::parse_define("url=https://example.org").unwrap();
+        params.define(name.as_str(), value);
+        let source = "print param(\"url\", \"https://default.com\");\nprint param(\"count\", 1);\n";
+        assert_eq!(printed_with(source, params).unwrap(), vec!["https://example.org", "3"]);
+    }
+
+    #[test]
+    fn defaults_apply_and_required_params_must_be_given() {
+        assert_eq!(printed_with("print param(\"count\", 1);\n", Params::new()).unwrap(), vec!["1"]);
+        assert!(printed_with("print param(\"count\");\n", Params::new()).is_none());
+    }
+
+    #[test]
+    fn defines_are_coerced_to_the_type_of_the_default() {
+        let mut params = Params::new();
+        let (name, value) = Params::parse_define("name=5").unwrap();
+        params.define(name.as_str(), value);
+        assert_eq!(printed_with("print param(\"name\", \"text\");\n", params.clone()).unwrap(), vec!["5"]);
+        assert!(printed_with("print param(\"name\", true);\n", params).is_none());
+    }
+}
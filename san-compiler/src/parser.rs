@@ -1,4 +1,6 @@
 use crate::ScannerRef;
+use crate::diagnostic::{Diagnostic, Severity};
+use crate::lint::AllowedLints;
 use crate::token::{Token, TokenType};
 
 #[derive(Clone)]
@@ -7,6 +9,12 @@ pub struct Parser {
     pub previous: Option<Token>,
     pub had_error: bool,
     pub panic_mode: bool,
+    pub diagnostics: Vec<Diagnostic>,
+    //diagnostics are only collected, not printed
+    pub quiet: bool,
+    pub allowed: AllowedLints,
+    //path printed with the diagnostics of an imported file
+    pub file_name: Option<String>,
 }
 
 impl Parser {
@@ -16,6 +24,10 @@ impl Parser {
             previous: None,
             had_error: false,
             panic_mode: false,
+            diagnostics: vec![],
+            quiet: false,
+            allowed: AllowedLints::default(),
+            file_name: None,
         }
     }
 
@@ -27,12 +39,12 @@ impl Parser {
             let token = scanner.scan_token();
             self.current = Some(token);
 
-            match self.current.as_ref().unwrap_or_else(|| { panic!("Parsed token is of type None. This is really weird!") }).token_type {
-                TokenType::Error(_) => {}
+            let message = match &self.current.as_ref().unwrap_or_else(|| { panic!("Parsed token is of type None. This is really weird!") }).token_type {
+                TokenType::Error(message) => message.clone(),
                 _ => break
-            }
+            };
 
-            self.error_at_current(self.current.as_ref().unwrap_or_else(|| { panic!("Parsed token is of type None. This is really weird!") }).get_token_string(scanner.source), scanner.source);
+            self.error_at_current(message, scanner.source);
         }
     }
 
@@ -46,20 +58,57 @@ impl Parser {
     }
 
     fn error_at_current(&mut self, message: String, source: &str) {
-        self.error_at(self.current.as_ref().unwrap_or_else(|| { panic!("Parsed token is of type None.") }), message, source);
+        let token = self.current.clone().unwrap_or_else(|| { panic!("Parsed token is of type None.") });
+        self.error_at(&token, message, source);
         self.panic_mode = true;
         self.had_error = true;
     }
 
     pub fn error(&mut self, message: String, source: &str) {
-        self.error_at(self.previous.as_ref().unwrap_or_else(|| { panic!("Parsed token is of type None.") }), message, source);
+        let token = self.previous.clone().unwrap_or_else(|| { panic!("Parsed token is of type None.") });
+        self.error_at(&token, message, source);
         self.panic_mode = true;
         self.had_error = true;
     }
 
-    fn error_at(&self, token: &Token, message: String, source: &str) {
+    //reports an error at the given token without entering panic mode, used for semantic errors
+    pub fn error_at_token(&mut self, token: &Token, message: String, source: &str) {
+        self.error_at(token, message, source);
+        self.had_error = true;
+    }
+
+    //warnings never fail the compilation and can be allowed per line with a pragma comment
+    pub fn warning_at(&mut self, token: &Token, lint: &str, message: String, source: &str) {
+        if self.allowed.is_allowed(token.line, lint) { return; }
+        //the lint name tells what to put into an allow pragma
+        let message = format!("{} [{}]", message, lint);
+        self.diagnostics.push(Diagnostic {
+            severity: Severity::Warning,
+            message: message.clone(),
+            line: token.line,
+            start_index: Some(token.start_index),
+            length: token.length,
+            file: token.file,
+        });
+        if self.quiet { return; }
+
+        eprintln!("[{}] Warning at {}: {}", self.location(token), token.get_token_string(source), message);
+    }
+
+    fn error_at(&mut self, token: &Token, message: String, source: &str) {
         if self.panic_mode { return; }
-        eprint!("[line {}] Error", token.line);
+        let is_scanner_error = matches!(token.token_type, TokenType::Error(_));
+        self.diagnostics.push(Diagnostic {
+            severity: Severity::Error,
+            message: message.clone(),
+            line: token.line,
+            start_index: if is_scanner_error { None } else { Some(token.start_index) },
+            length: if is_scanner_error { 0 } else { token.length },
+            file: token.file,
+        });
+        if self.quiet { return; }
+
+        eprint!("[{}] Error", self.location(token));
 
         if token.token_type == TokenType::EOF {
             eprint!(" at end");
@@ -71,4 +120,11 @@ impl Parser {
 
         eprintln!(": {}", message);
     }
-}
\ No newline at end of file
+
+    fn location(&self, token: &Token) -> String {
+        match &self.file_name {
+            Some(file_name) => format!("{} line {}", file_name, token.line),
+            None => format!("line {}", token.line),
+        }
+    }
+}
@@ -0,0 +1,157 @@
+use std::io;
+use std::io::{BufRead, Write};
+use san_common::value::FunctionType;
+use crate::compiler::{Compiler, Constant};
+use crate::disassembler::disassemble_function;
+use crate::layout::KeyboardLayout;
+use crate::scanner::Scanner;
+use crate::simulator::{SimValue, Simulator};
+use crate::token::TokenType;
+use crate::trace::{Trace, TraceEvent};
+
+const HELP: &str = "Enter statements, or an expression without ';' to see its value.
+Commands:
+    :tokens    Toggle printing the tokens of each entry
+    :disasm    Toggle printing the bytecode of each entry
+    :globals   List the globals defined so far
+    :reset     Forget all globals and start over
+    :help      Print this help
+    :quit      Leave the REPL";
+
+pub struct Repl {
+    layout: &'static KeyboardLayout,
+    simulator: Simulator,
+    globals: Vec<String>,
+    constants: Vec<Constant>,
+    show_tokens: bool,
+    show_disasm: bool,
+}
+
+impl Repl {
+    pub fn new(layout: &'static KeyboardLayout) -> Repl {
+        Repl {
+            layout,
+            simulator: Simulator::new(layout),
+            globals: vec![],
+            constants: vec![],
+            show_tokens: false,
+            show_disasm: false,
+        }
+    }
+
+    pub fn run<R: BufRead, W: Write>(&mut self, input: &mut R, output: &mut W) -> io::Result<()> {
+        writeln!(output, "sanc {} REPL, type :help for help", env!("CARGO_PKG_VERSION"))?;
+        let mut entry = String::new();
+
+        loop {
+            write!(output, "{}", if entry.is_empty() { "> " } else { "... " })?;
+            output.flush()?;
+
+            let mut line = String::new();
+            if input.read_line(&mut line)? == 0 {
+                writeln!(output)?;
+                return Ok(());
+            }
+
+            if entry.is_empty() {
+                match line.trim() {
+                    "" => continue,
+                    ":quit" | ":q" => return Ok(()),
+                    command if command.starts_with(':') => {
+                        self.command(command, output)?;
+                        continue;
+                    }
+                    _ => ()
+                }
+            }
+
+            entry.push_str(line.as_str());
+            if Repl::is_complete(entry.as_str()) {
+                self.evaluate(entry.as_str(), output)?;
+                entry.clear();
+            }
+        }
+    }
+
+    fn command<W: Write>(&mut self, command: &str, output: &mut W) -> io::Result<()> {
+        match command {
+            ":tokens" => {
+                self.show_tokens = !self.show_tokens;
+                writeln!(output, "Tokens {}", if self.show_tokens { "on" } else { "off" })
+            }
+            ":disasm" => {
+                self.show_disasm = !self.show_disasm;
+                writeln!(output, "Bytecode {}", if self.show_disasm { "on" } else { "off" })
+            }
+            ":globals" => writeln!(output, "{}", self.globals.join(", ")),
+            ":reset" => {
+                self.simulator = Simulator::new(self.layout);
+                self.globals.clear();
+                self.constants.clear();
+                writeln!(output, "Environment reset")
+            }
+            ":help" => writeln!(output, "{}", HELP),
+            _ => writeln!(output, "Unknown command '{}', type :help for help", command),
+        }
+    }
+
+    //an entry is complete once every block it opened is closed again
+    fn is_complete(entry: &str) -> bool {
+        let mut scanner = Scanner::new(entry);
+        let mut depth = 0;
+        loop {
+            match scanner.scan_token().token_type {
+                TokenType::LeftBrace => depth += 1,
+                TokenType::RightBrace => depth -= 1,
+                TokenType::EOF => return depth <= 0,
+                _ => ()
+            }
+        }
+    }
+
+    //compiles the entry against the globals of the earlier entries and runs it in the shared simulator
+    pub fn evaluate<W: Write>(&mut self, entry: &str, output: &mut W) -> io::Result<()> {
+        if self.show_tokens {
+            Scanner::new(entry).tokenize_source(output)?;
+        }
+
+        let mut compiler = Compiler::new(entry, FunctionType::Script);
+        compiler.set_repl(true);
+        compiler.set_globals(self.globals.clone());
+        compiler.set_constants(self.constants.clone());
+        let function = match compiler.compile() {
+            Some(function) => function,
+            None => return Ok(()),
+        };
+        self.globals = compiler.globals().to_vec();
+        self.constants = compiler.constants().to_vec();
+
+        if self.show_disasm {
+            write!(output, "{}", disassemble_function(&function))?;
+        }
+
+        let start = self.simulator.trace.events.len();
+        let result = self.simulator.run(function);
+        let trace = Trace { events: self.simulator.trace.events[start..].to_vec() };
+
+        for event in &trace.events {
+            if let TraceEvent::Print(value) = event {
+                writeln!(output, "{}", value)?;
+            }
+        }
+
+        let text = trace.render_text(self.layout);
+        if !text.is_empty() {
+            writeln!(output, "keys: {}", text.trim_end())?;
+        }
+        if trace.duration() > 0 {
+            writeln!(output, "duration: {} ms", trace.duration())?;
+        }
+
+        match result {
+            Ok(SimValue::Nil) => Ok(()),
+            Ok(value) => writeln!(output, "= {}", value),
+            Err(e) => writeln!(output, "{}", e),
+        }
+    }
+}
@@ -1,41 +1,581 @@
 use std::env;
-use std::fs::File;
+use std::fmt;
+use std::fs;
 use std::io;
-use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
 use std::process::exit;
 use postcard::to_allocvec;
-use san_common::value::FunctionType;
+use san_common::value::{FunctionData, FunctionType, Value};
+use crate::backend::{arduino_sketch, check_lowerable, circuitpython_script, Backend};
 use crate::compiler::Compiler;
+use crate::diagnostic::Severity;
+use crate::disassembler::disassemble_function;
+use crate::ducky::translate;
+use crate::estimate::estimate;
+use crate::export::export_ducky;
+use crate::fmt::format_source;
+use crate::golden::{find_sources, run_golden_tests};
+use crate::layout::{layout_by_name, KeyboardLayout};
+use crate::natives::missing_on_device;
+use crate::optimizer::optimize;
+use crate::params::{Params, TARGETS, TARGET_PARAM};
+use crate::repl::Repl;
+use crate::scanner::Scanner;
+use crate::shake::tree_shake;
+use crate::simulator::Simulator;
+
+pub const EXIT_TEST_FAILURE: i32 = 1;
+pub const EXIT_USAGE: i32 = 64;
+pub const EXIT_COMPILE_ERROR: i32 = 65;
+pub const EXIT_RUNTIME_ERROR: i32 = 70;
+pub const EXIT_IO_ERROR: i32 = 74;
+
+const USAGE: &str = "Usage: sanc <command> [options]
+
+Commands:
+    build <source>       Compile a payload to SanVM bytecode
+        -o <path>        Output path (defaults to the source path with a .bin extension)
+        -O               Optimize the compiled bytecode
+        --emit <kind>    Output kind: bin (default), disasm or tokens
+        -D <name=value>  Set a compile-time parameter, overrides [params] in config.toml
+        -I <dir>         Also look for imported files in dir, accepted by every command that compiles
+        --target <os>    Compile the code for windows, linux or macos, same as -D target=<os>
+        --backend <name> Code to generate: sanvm (bytecode, the default), arduino or circuitpython
+    check <source>       Parse and analyze a payload without writing output
+        --deny-warnings  Fail when there are warnings, also accepted by build
+    tokens <source>      Print the tokens of a payload
+    disasm <source>      Print the compiled bytecode of a payload
+    run <source>         Run a payload in the host simulator (alias: sim)
+        --layout <name>  Keyboard layout of the target: us (default), uk or de
+        --raw            Also print the raw HID trace
+    repl                 Evaluate statements interactively in the host simulator
+        --layout <name>  Keyboard layout of the target
+    estimate <source>    Estimate how long a payload runs
+    export <source>      Export a payload for other hardware
+        --format <name>  Output format: ducky (DuckyScript 1.0, the default)
+        -o <path>        Output path (defaults to the source path with a .txt extension)
+    fmt <paths...>       Format payloads in place
+        --check          Only report files that are not formatted
+    import-ducky <file>  Translate a Rubber Ducky script to SanScript
+        -o <path>        Output path (defaults to the script path with a .san extension)
+    test [path]          Run test blocks and golden files of every payload under path (default: Payloads)
+        --bless          Overwrite the .expected files with the current output
+
+Options:
+    -h, --help           Print this help
+    -V, --version        Print the compiler version";
+
+#[derive(Debug)]
+pub enum CliError {
+    Usage(String),
+    Compile,
+    Runtime(String),
+    Io(io::Error),
+    TestFailure,
+    Unformatted,
+}
+
+impl CliError {
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            CliError::Usage(_) => EXIT_USAGE,
+            CliError::Compile => EXIT_COMPILE_ERROR,
+            CliError::Runtime(_) => EXIT_RUNTIME_ERROR,
+            CliError::Io(_) => EXIT_IO_ERROR,
+            CliError::TestFailure | CliError::Unformatted => EXIT_TEST_FAILURE,
+        }
+    }
+}
+
+impl fmt::Display for CliError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CliError::Usage(message) => write!(f, "{}\n\n{}", message, USAGE),
+            CliError::Compile => write!(f, "Code failed to compile!"),
+            CliError::Runtime(message) => write!(f, "{}", message),
+            CliError::Io(e) => write!(f, "{}", e),
+            CliError::TestFailure => write!(f, "Some tests failed"),
+            CliError::Unformatted => write!(f, "Some files are not formatted"),
+        }
+    }
+}
+
+impl From<io::Error> for CliError {
+    fn from(e: io::Error) -> Self {
+        CliError::Io(e)
+    }
+}
+
+#[derive(Copy, Clone, PartialEq)]
+pub enum ExportFormat {
+    Ducky,
+}
+
+#[derive(Copy, Clone, PartialEq)]
+pub enum Emit {
+    Binary,
+    Disasm,
+    Tokens,
+}
+
+pub struct Options {
+    pub inputs: Vec<String>,
+    pub output: Option<String>,
+    pub optimize: bool,
+    pub emit: Emit,
+    pub format: ExportFormat,
+    pub backend: Backend,
+    pub layout: &'static KeyboardLayout,
+    pub bless: bool,
+    pub raw: bool,
+    pub check: bool,
+    pub deny_warnings: bool,
+    pub defines: Vec<(String, Value)>,
+    pub search_paths: Vec<PathBuf>,
+}
+
+impl Options {
+    pub fn parse(args: &[String]) -> Result<Options, CliError> {
+        let mut options = Options {
+            inputs: vec![],
+            output: None,
+            optimize: false,
+            emit: Emit::Binary,
+            format: ExportFormat::Ducky,
+            backend: Backend::SanVm,
+            layout: layout_by_name("us").expect("US layout is always available"),
+            bless: false,
+            raw: false,
+            check: false,
+            deny_warnings: false,
+            defines: vec![],
+            search_paths: vec![],
+        };
+
+        let mut args = args.iter();
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "-o" | "--output" => options.output = Some(Options::value(arg, args.next())?),
+                "-O" => options.optimize = true,
+                "--emit" => {
+                    options.emit = match Options::value(arg, args.next())?.as_str() {
+                        "bin" => Emit::Binary,
+                        "disasm" => Emit::Disasm,
+                        "tokens" => Emit::Tokens,
+                        other => return Err(CliError::Usage(format!("Unknown emit kind '{}'", other))),
+                    }
+                }
+                "--format" => {
+                    options.format = match Options::value(arg, args.next())?.as_str() {
+                        "ducky" => ExportFormat::Ducky,
+                        other => return Err(CliError::Usage(format!("Unknown export format '{}'", other))),
+                    }
+                }
+                "--backend" => {
+                    let name = Options::value(arg, args.next())?;
+                    options.backend = Backend::by_name(name.as_str())
+                        .ok_or_else(|| CliError::Usage(format!("Unknown backend '{}', expected sanvm, arduino or circuitpython", name)))?;
+                }
+                "--layout" => {
+                    let name = Options::value(arg, args.next())?;
+                    options.layout = layout_by_name(name.as_str())
+                        .ok_or_else(|| CliError::Usage(format!("Unknown keyboard layout '{}'", name)))?;
+                }
+                "--bless" => options.bless = true,
+                "--raw" => options.raw = true,
+                "--check" => options.check = true,
+                "--deny-warnings" => options.deny_warnings = true,
+                "--target" => {
+                    let target = Options::value(arg, args.next())?;
+                    if !TARGETS.contains(&target.as_str()) {
+                        return Err(CliError::Usage(format!("Unknown target '{}', expected one of {}", target, TARGETS.join(", "))));
+                    }
+                    options.defines.push((TARGET_PARAM.to_string(), Value::ValString(target)));
+                }
+                "-I" => options.search_paths.push(PathBuf::from(Options::value(arg, args.next())?)),
+                "-D" => options.defines.push(Params::parse_define(Options::value(arg, args.next())?.as_str()).map_err(CliError::Usage)?),
+                define if define.starts_with("-D") => options.defines.push(Params::parse_define(&define[2..]).map_err(CliError::Usage)?),
+                flag if flag.starts_with('-') => return Err(CliError::Usage(format!("Unknown option '{}'", flag))),
+                input => options.inputs.push(input.to_string()),
+            }
+        }
+
+        Ok(options)
+    }
+
+    fn value(flag: &str, value: Option<&String>) -> Result<String, CliError> {
+        value.cloned().ok_or_else(|| CliError::Usage(format!("Option '{}' expects a value", flag)))
+    }
+
+    fn single_input(&self) -> Result<&str, CliError> {
+        match self.inputs.as_slice() {
+            [input] => Ok(input.as_str()),
+            [] => Err(CliError::Usage(String::from("Expected a source path"))),
+            _ => Err(CliError::Usage(String::from("Expected a single source path"))),
+        }
+    }
+}
 
 pub fn run() {
-    if env::args().len() == 3 {
-        let args: Vec<String> = env::args().collect();
-        if let Err(e) = run_file(args[1].as_str(), args[2].as_str()) {
-            eprintln!("{}", e.to_string());
-            exit(1);
+    let args: Vec<String> = env::args().skip(1).collect();
+    if let Err(e) = run_command(&args) {
+        eprintln!("{}", e);
+        exit(e.exit_code());
+    }
+}
+
+pub fn run_command(args: &[String]) -> Result<(), CliError> {
+    let command = match args.first() {
+        Some(command) => command.as_str(),
+        None => return Err(CliError::Usage(String::from("Expected a command"))),
+    };
+
+    match command {
+        "-h" | "--help" | "help" => {
+            println!("{}", USAGE);
+            return Ok(());
+        }
+        "-V" | "--version" => {
+            println!("sanc {}", env!("CARGO_PKG_VERSION"));
+            return Ok(());
+        }
+        _ => ()
+    }
+    if args[1..].iter().any(|arg| arg == "-h" || arg == "--help") {
+        if let Some(usage) = command_usage(command) {
+            println!("{}", usage);
+            return Ok(());
         }
-    } else {
-        eprintln!("Usage: sanc <source path> <destination path>");
-        exit(1);
     }
+
+    let options = Options::parse(&args[1..])?;
+    match command {
+        "build" => build(&options),
+        "check" => check(&options),
+        "tokens" => tokens(&options),
+        "disasm" => disasm(&options),
+        "run" | "sim" => simulate(&options),
+        "repl" => repl(&options),
+        "estimate" => estimate_duration(&options),
+        "export" => export(&options),
+        "test" => test(&options),
+        "fmt" => format(&options),
+        "import-ducky" => import_ducky(&options),
+        //the original `sanc <source path> <destination path>` form that SanTool relies on
+        _ if args.len() == 2 && !command.starts_with('-') => match run_file(args[0].as_str(), args[1].as_str())? {
+            true => Ok(()),
+            false => Err(CliError::Compile),
+        },
+        _ => Err(CliError::Usage(format!("Unknown command '{}'", command))),
+    }
+}
+
+//the section of USAGE that describes the command and its options
+fn command_usage(command: &str) -> Option<String> {
+    let command = if command == "sim" { "run" } else { command };
+    let mut lines = USAGE.lines().skip_while(|line| {
+        line.starts_with("        ") || !line.starts_with("    ") || line.split_whitespace().next() != Some(command)
+    });
+    let description = lines.next()?;
+    let options: Vec<&str> = lines.take_while(|line| line.starts_with("        ")).collect();
+
+    let mut usage = format!("Usage: sanc {} [options]\n\n{}", command, description);
+    for option in options {
+        usage.push('\n');
+        usage.push_str(option);
+    }
+    Some(usage)
 }
 
-pub fn run_file(source_path: &str, dest_path: &str) -> io::Result<()> {
-    read_file(source_path, dest_path);
+//returns whether the payload compiled, compile errors are reported on stderr like in `sanc build`
+pub fn run_file(source_path: &str, dest_path: &str) -> io::Result<bool> {
+    let source = fs::read_to_string(source_path)?;
+    let mut compiler = Compiler::new(source.as_str(), FunctionType::Script);
+    compiler.set_path(Path::new(source_path));
+    compiler.set_params(Params::from_config(Path::new(source_path))?);
+    let function = match compiler.compile() {
+        Some(function) => tree_shake(&function),
+        None => return Ok(false),
+    };
+    if !report_device_natives(&function, source_path) {
+        return Ok(false);
+    }
+    write_binary(&function, dest_path)?;
+    println!("Code compiled successfully!");
+    Ok(true)
+}
+
+fn write_binary(function: &FunctionData, dest_path: &str) -> io::Result<()> {
+    let output = to_allocvec(function)
+        .map_err(|e| io::Error::new(io::ErrorKind::Other, format!("Error serializing compiler result: {}", e)))?;
+    fs::write(dest_path, output.as_slice())
+}
+
+fn compile_file(source_path: &str, options: &Options) -> Result<FunctionData, CliError> {
+    let source = fs::read_to_string(source_path)?;
+    let mut compiler = Compiler::new(source.as_str(), FunctionType::Script);
+    prepare(&mut compiler, source_path, options)?;
+    let function = compiler.compile().ok_or(CliError::Compile)?;
+    deny_warnings(&compiler, options)?;
+    let function = tree_shake(&function);
+    if options.optimize {
+        return Ok(optimize(&function));
+    }
+    Ok(function)
+}
+
+//imports are resolved relative to the payload, -D flags take precedence over its config
+fn prepare(compiler: &mut Compiler, source_path: &str, options: &Options) -> Result<(), CliError> {
+    compiler.set_path(Path::new(source_path));
+    for search_path in &options.search_paths {
+        compiler.add_search_path(search_path.clone());
+    }
+
+    let mut params = Params::from_config(Path::new(source_path))?;
+    for (name, value) in &options.defines {
+        params.define(name.as_str(), value.clone());
+    }
+    compiler.set_params(params);
+    Ok(())
+}
+
+fn deny_warnings(compiler: &Compiler, options: &Options) -> Result<(), CliError> {
+    let warnings = compiler.diagnostics().iter().filter(|diagnostic| diagnostic.severity == Severity::Warning).count();
+    if options.deny_warnings && warnings > 0 {
+        eprintln!("{} warnings found and warnings are denied", warnings);
+        return Err(CliError::Compile);
+    }
     Ok(())
 }
 
-fn read_file(source_path: &str, dest_path: &str) {
-    let mut source_file = File::open(source_path).unwrap_or_else(|e|{panic!("Error opening file at path {}: {}", source_path, e.to_string())});
-    let mut source: String = String::from("");
-    source_file.read_to_string(&mut source).unwrap_or_else(|e|{panic!("Error reading file content: {}", e.to_string())});
+fn build(options: &Options) -> Result<(), CliError> {
+    let source_path = options.single_input()?;
+    if options.emit == Emit::Tokens {
+        return tokens(options);
+    }
+    if options.backend != Backend::SanVm {
+        return generate(options);
+    }
+
+    let function = compile_file(source_path, options)?;
+    match options.emit {
+        Emit::Disasm => {
+            let disassembly = disassemble_function(&function);
+            match &options.output {
+                Some(output) => fs::write(output, disassembly)?,
+                None => print!("{}", disassembly),
+            }
+        }
+        _ => {
+            let dest_path = match &options.output {
+                Some(output) => output.clone(),
+                None => Path::new(source_path).with_extension("bin").to_string_lossy().to_string(),
+            };
+            check_device_natives(&function, source_path)?;
+            write_binary(&function, dest_path.as_str())?;
+            println!("Code compiled successfully!");
+        }
+    }
+    Ok(())
+}
+
+fn check_device_natives(function: &FunctionData, source_path: &str) -> Result<(), CliError> {
+    match report_device_natives(function, source_path) {
+        true => Ok(()),
+        false => Err(CliError::Compile),
+    }
+}
+
+//returns whether SanVM implements every native the payload calls
+fn report_device_natives(function: &FunctionData, source_path: &str) -> bool {
+    let missing = missing_on_device(function);
+    for (line, name) in &missing {
+        eprintln!("{}: line {}: SanVM does not implement '{}' yet", source_path, line, name);
+    }
+    if !missing.is_empty() {
+        eprintln!("Run the payload with `sanc run` or build it with the arduino or circuitpython backend");
+    }
+    missing.is_empty()
+}
+
+//the program is folded and run once in the simulator, the generated code replays that run on the board
+fn generate(options: &Options) -> Result<(), CliError> {
+    let source_path = options.single_input()?;
+    let function = optimize(&compile_file(source_path, options)?);
+    let errors = check_lowerable(&function);
+    for error in &errors {
+        eprintln!("{}: {}", source_path, error);
+    }
+    if !errors.is_empty() {
+        eprintln!("The {} backend only supports payloads that run the same way every time", options.backend.name());
+        return Err(CliError::Compile);
+    }
+
+    let mut simulator = Simulator::new(options.layout);
+    simulator.run(function).map_err(|e| CliError::Runtime(e.to_string()))?;
+    let code = match options.backend {
+        Backend::Arduino => arduino_sketch(&simulator.trace, options.layout, source_path),
+        _ => circuitpython_script(&simulator.trace, options.layout, source_path),
+    };
+    let code = code.map_err(|e| {
+        eprintln!("{}: {}", source_path, e);
+        CliError::Compile
+    })?;
+    let dest_path = match &options.output {
+        Some(output) => output.clone(),
+        None => Path::new(source_path).with_extension(options.backend.extension()).to_string_lossy().to_string(),
+    };
+    fs::write(&dest_path, code)?;
+    println!("Generated {}", dest_path);
+    Ok(())
+}
+
+fn check(options: &Options) -> Result<(), CliError> {
+    let source_path = options.single_input()?;
+    let source = fs::read_to_string(source_path)?;
     let mut compiler = Compiler::new(source.as_str(), FunctionType::Script);
-    if let Some(function) = compiler.compile() {
-        let output = to_allocvec(&function).unwrap_or_else(|e|{panic!("Error serializing compiler result: {}", e)});
-        let mut file = File::create(dest_path).unwrap_or_else(|e|{panic!("Error opening file at path {}: {}", dest_path, e.to_string())});
-        file.write_all(output.as_slice()).expect("Error writing serialized data to a file");
-        println!("Code compiled successfully!");
+    compiler.set_quiet(true);
+    prepare(&mut compiler, source_path, options)?;
+    let function = compiler.compile();
+
+    for diagnostic in compiler.diagnostics() {
+        match compiler.source_file(diagnostic.file) {
+            Some(file) => eprintln!("{}: {}", file.path.display(), diagnostic.render(file.source.as_str())),
+            None => eprintln!("{}", diagnostic.render(source.as_str())),
+        }
+    }
+    if function.is_none() {
+        return Err(CliError::Compile);
+    }
+    deny_warnings(&compiler, options)?;
+    println!("No errors found");
+    Ok(())
+}
+
+fn tokens(options: &Options) -> Result<(), CliError> {
+    let source = fs::read_to_string(options.single_input()?)?;
+    Scanner::new(source.as_str()).tokenize_source(&mut io::stdout())?;
+    Ok(())
+}
+
+fn disasm(options: &Options) -> Result<(), CliError> {
+    let function = compile_file(options.single_input()?, options)?;
+    print!("{}", disassemble_function(&function));
+    Ok(())
+}
+
+fn simulate(options: &Options) -> Result<(), CliError> {
+    let function = compile_file(options.single_input()?, options)?;
+    let mut simulator = Simulator::new(options.layout);
+    let result = simulator.run(function);
+
+    if options.raw {
+        print!("{}", simulator.trace);
+        println!();
+    }
+    println!("{}", simulator.trace.render_text(options.layout));
+    println!("Virtual duration: {} ms", simulator.trace.duration());
+    result.map(|_| ()).map_err(|e| CliError::Runtime(e.to_string()))
+}
+
+fn repl(options: &Options) -> Result<(), CliError> {
+    let stdin = io::stdin();
+    Repl::new(options.layout).run(&mut stdin.lock(), &mut io::stdout())?;
+    Ok(())
+}
+
+fn estimate_duration(options: &Options) -> Result<(), CliError> {
+    let function = compile_file(options.single_input()?, options)?;
+    println!("Estimated duration: {}", estimate(function, options.layout));
+    Ok(())
+}
+
+//the program is folded first, so payloads that only branch on constants still count as straight-line
+fn export(options: &Options) -> Result<(), CliError> {
+    let source_path = options.single_input()?;
+    let function = optimize(&compile_file(source_path, options)?);
+    let mut simulator = Simulator::new(options.layout);
+    simulator.run(function.clone()).map_err(|e| CliError::Runtime(e.to_string()))?;
+
+    let script = match options.format {
+        ExportFormat::Ducky => export_ducky(&function, &simulator.trace, options.layout, source_path),
+    };
+    let script = script.map_err(|e| {
+        eprintln!("{}: {}", source_path, e);
+        CliError::Compile
+    })?;
+    let dest_path = match &options.output {
+        Some(output) => output.clone(),
+        None => Path::new(source_path).with_extension("txt").to_string_lossy().to_string(),
+    };
+    fs::write(&dest_path, script)?;
+    println!("Exported {} to {}", source_path, dest_path);
+    Ok(())
+}
+
+fn test(options: &Options) -> Result<(), CliError> {
+    let path = match options.inputs.as_slice() {
+        [] => "Payloads",
+        [path] => path.as_str(),
+        _ => return Err(CliError::Usage(String::from("Expected a single payload path"))),
+    };
+
+    if run_golden_tests(Path::new(path), options.layout, options.bless)? {
+        Ok(())
     } else {
-        println!("Code failed to compile!");
+        Err(CliError::TestFailure)
     }
-}
\ No newline at end of file
+}
+
+//untranslatable lines end up as comments in the output, so the rest of the script is still usable
+fn import_ducky(options: &Options) -> Result<(), CliError> {
+    let script_path = options.single_input()?;
+    let script = fs::read_to_string(script_path)?;
+    let translation = translate(script.as_str());
+    let dest_path = match &options.output {
+        Some(output) => output.clone(),
+        None => Path::new(script_path).with_extension("san").to_string_lossy().to_string(),
+    };
+    fs::write(&dest_path, translation.source)?;
+
+    for unsupported in &translation.unsupported {
+        eprintln!("{}: {}", script_path, unsupported);
+    }
+    if !translation.unsupported.is_empty() {
+        eprintln!("{} lines could not be translated and were left as comments in {}", translation.unsupported.len(), dest_path);
+        return Err(CliError::Compile);
+    }
+    println!("Translated {} to {}", script_path, dest_path);
+    Ok(())
+}
+
+fn format(options: &Options) -> Result<(), CliError> {
+    if options.inputs.is_empty() {
+        return Err(CliError::Usage(String::from("Expected at least one path to format")));
+    }
+
+    let mut unformatted = false;
+    for input in &options.inputs {
+        for source_path in find_sources(Path::new(input))? {
+            let source = fs::read_to_string(&source_path)?;
+            let formatted = format_source(source.as_str()).map_err(|e| {
+                eprintln!("{}: {}", source_path.display(), e);
+                CliError::Compile
+            })?;
+
+            if formatted == source {
+                continue;
+            }
+
+            if options.check {
+                unformatted = true;
+                println!("{} is not formatted", source_path.display());
+            } else {
+                fs::write(&source_path, formatted)?;
+                println!("Formatted {}", source_path.display());
+            }
+        }
+    }
+
+    if unformatted { Err(CliError::Unformatted) } else { Ok(()) }
+}
@@ -1,4 +1,7 @@
+use std::io;
+use std::io::Write;
 use san_common::keycodes::{HID_KEY_STRINGS, MOUSE_BUTTON_STRINGS};
+use crate::media::media_key;
 use crate::token::{Token, TokenType};
 
 pub struct Scanner<'a> {
@@ -8,6 +11,9 @@ pub struct Scanner<'a> {
     current_index: usize,
     pub source: &'a str,
     line: usize,
+    //whitespace and comments are returned as tokens instead of being skipped
+    keep_trivia: bool,
+    file: usize,
 }
 
 impl<'a> Scanner<'a> {
@@ -17,35 +23,55 @@ impl<'a> Scanner<'a> {
             current_index: 0,
             source,
             line: 1,
+            keep_trivia: false,
+            file: 0,
         }
     }
 
-    pub fn tokenize_source(&mut self) {
+    //tokens of an imported file carry its file id
+    pub fn set_file(&mut self, file: usize) {
+        self.file = file;
+    }
+
+    pub fn with_trivia(source: &'a str) -> Scanner {
+        let mut scanner = Scanner::new(source);
+        scanner.keep_trivia = true;
+        scanner
+    }
+
+    pub fn tokenize_source<W: Write>(&mut self, output: &mut W) -> io::Result<()> {
         let mut line: isize = -1;
 
-        println!("\x1B[4mLINE |   TOKEN TYPE   | TOKEN  \x1B[0m");
+        writeln!(output, "\x1B[4mLINE |   TOKEN TYPE   | TOKEN  \x1B[0m")?;
 
         loop {
             let token = self.scan_token();
             if token.line as isize != line {
-                print!("{:<5}  ", token.line);
+                write!(output, "{:<5}  ", token.line)?;
                 line = token.line as isize;
             } else {
-                print!("|      ");
+                write!(output, "|      ")?;
             }
 
-            println!("{:16} '{}'", token.token_type.to_string(), token.get_token_string(self.source));
+            writeln!(output, "{:16} '{}'", token.token_type.to_string(), token.get_token_string(self.source))?;
 
             if token.token_type == TokenType::EOF {
                 break;
             }
         }
 
-        println!();
+        writeln!(output)
     }
 
     pub fn scan_token(&mut self) -> Token {
-        self.skip_whitespace();
+        if self.keep_trivia {
+            self.start_index = self.current_index;
+            if let Some(token) = self.trivia() {
+                return token;
+            }
+        } else {
+            self.skip_whitespace();
+        }
         self.start_index = self.current_index;
 
         if self.is_at_end() {
@@ -75,7 +101,16 @@ impl<'a> Scanner<'a> {
             ';' => return self.make_token(TokenType::Semicolon),
             '.' => return self.make_token(TokenType::Dot),
             ',' => return self.make_token(TokenType::Comma),
-            '-' => return self.make_token(TokenType::Minus),
+            ':' => return self.make_token(TokenType::Colon),
+            '#' => return self.make_token(TokenType::Hash),
+            '[' => return self.make_token(TokenType::LeftBracket),
+            ']' => return self.make_token(TokenType::RightBracket),
+            '-' => {
+                if self.match_next('>') {
+                    return self.make_token(TokenType::Arrow);
+                }
+                return self.make_token(TokenType::Minus);
+            }
             '+' => return self.make_token(TokenType::Plus),
             '/' => return self.make_token(TokenType::Slash),
             '*' => return self.make_token(TokenType::Star),
@@ -114,29 +149,40 @@ impl<'a> Scanner<'a> {
     }
 
     pub fn skip_whitespace(&mut self) {
+        loop {
+            self.start_index = self.current_index;
+            if self.trivia().is_none() {
+                return;
+            }
+        }
+    }
+
+    //scans a run of whitespace, a single newline or a line comment
+    pub fn trivia(&mut self) -> Option<Token> {
         if self.is_at_end() {
-            return;
+            return None;
         }
 
-        loop {
-            let c = self.peek();
-            match c {
-                ' ' | '\t' | '\r' => { self.advance(); }
-                '\n' => {
-                    self.line += 1;
+        match self.peek() {
+            ' ' | '\t' | '\r' => {
+                while matches!(self.peek(), ' ' | '\t' | '\r') {
                     self.advance();
                 }
-                '/' => {
-                    if self.peek_next() == '/' {
-                        while !self.is_at_end() && self.peek() != '\n' {
-                            self.advance();
-                        }
-                    } else {
-                        return;
-                    }
+                Some(self.make_token(TokenType::Whitespace))
+            }
+            '\n' => {
+                self.advance();
+                let token = self.make_token(TokenType::Newline);
+                self.line += 1;
+                Some(token)
+            }
+            '/' if self.peek_next() == '/' => {
+                while !self.is_at_end() && self.peek() != '\n' {
+                    self.advance();
                 }
-                _ => return
+                Some(self.make_token(TokenType::Comment))
             }
+            _ => None
         }
     }
 
@@ -157,17 +203,26 @@ impl<'a> Scanner<'a> {
     }
 
     pub fn make_token(&self, token_type: TokenType) -> Token {
-        Token::new(token_type, self.start_index, self.current_index - self.start_index, self.line)
+        let mut token = Token::new(token_type, self.start_index, self.current_index - self.start_index, self.line);
+        token.file = self.file;
+        token
     }
 
     pub fn error_token(&self, message: &'a str) -> Token {
-        Token::new(TokenType::Error(message.to_string()), 0, message.len(), self.line)
+        let mut token = Token::new(TokenType::Error(message.to_string()), 0, message.len(), self.line);
+        token.file = self.file;
+        token
     }
 
     pub fn hid(&mut self) -> Token {
         while Scanner::is_capital(self.peek()) || Scanner::is_digit(self.peek()) || self.peek().eq(&'_') {
             self.advance();
         }
+
+        //mixed case names like `Number` are identifiers, not key constants
+        if Scanner::is_alpha(self.peek()) {
+            return self.identifier();
+        }
         self.make_token(self.hid_type())
     }
 
@@ -186,6 +241,10 @@ impl<'a> Scanner<'a> {
             }
         }
 
+        if media_key(&self.source[self.start_index..self.current_index]).is_some() {
+            return TokenType::MediaKey;
+        }
+
         for mouse_key in MOUSE_BUTTON_STRINGS {
             if self.current_index - self.start_index == mouse_key.len() && &self.source[self.start_index..self.current_index] == mouse_key {
                 return TokenType::MouseButton;
@@ -198,15 +257,75 @@ impl<'a> Scanner<'a> {
     pub fn identifier_type(&self) -> TokenType {
         let start_char = self.source.chars().nth(self.start_index).unwrap_or_else(|| { panic!("Tried to index source code outside of its bounds!") });
         return match start_char {
-            'a' => self.check_keyword(1, 2, "nd", TokenType::And),
+            'a' => {
+                if self.current_index - self.start_index > 1 {
+                    let second_char = self.source.chars().nth(self.start_index + 1).unwrap_or_else(|| { panic!("Tried to index source code outside of its bounds!") });
+                    return match second_char {
+                        'n' => self.check_keyword(2, 1, "d", TokenType::And),
+                        's' => {
+                            if self.current_index - self.start_index == 2 {
+                                return TokenType::As;
+                            }
+
+                            self.check_keyword(2, 4, "sert", TokenType::Assert)
+                        }
+                        _ => TokenType::Identifier
+                    };
+                }
+
+                TokenType::Identifier
+            }
+            'c' => self.check_keyword(1, 4, "onst", TokenType::Const),
             'e' => self.check_keyword(1, 3, "lse", TokenType::Else),
-            'i' => self.check_keyword(1, 1, "f", TokenType::If),
+            'h' => self.check_keyword(1, 3, "old", TokenType::Hold),
+            'i' => {
+                if self.current_index - self.start_index > 1 {
+                    let second_char = self.source.chars().nth(self.start_index + 1).unwrap_or_else(|| { panic!("Tried to index source code outside of its bounds!") });
+                    return match second_char {
+                        'f' => {
+                            if self.current_index - self.start_index == 2 {
+                                return TokenType::If;
+                            }
+
+                            TokenType::Identifier
+                        }
+                        'm' => self.check_keyword(2, 4, "port", TokenType::Import),
+                        _ => TokenType::Identifier
+                    };
+                }
+
+                TokenType::Identifier
+            }
             'l' => self.check_keyword(1, 2, "et", TokenType::Let),
             'n' => self.check_keyword(1, 2, "il", TokenType::Nil),
             'o' => self.check_keyword(1, 1, "r", TokenType::Or),
-            'p' => self.check_keyword(1, 4, "rint", TokenType::Print),
+            'p' => {
+                if self.current_index - self.start_index > 1 {
+                    let second_char = self.source.chars().nth(self.start_index + 1).unwrap_or_else(|| { panic!("Tried to index source code outside of its bounds!") });
+                    return match second_char {
+                        'a' => self.check_keyword(2, 3, "ram", TokenType::Param),
+                        'r' => self.check_keyword(2, 3, "int", TokenType::Print),
+                        _ => TokenType::Identifier
+                    };
+                }
+
+                TokenType::Identifier
+            }
             'r' => self.check_keyword(1, 5, "eturn", TokenType::Return),
-            't' => self.check_keyword(1, 3, "rue", TokenType::True),
+            't' => {
+                if self.current_index - self.start_index > 1 {
+                    let second_char = self.source.chars().nth(self.start_index + 1).unwrap_or_else(|| { panic!("Tried to index source code outside of its bounds!") });
+                    return match second_char {
+                        'a' => self.check_keyword(2, 4, "rget", TokenType::Target),
+                        'e' => self.check_keyword(2, 2, "st", TokenType::Test),
+                        'r' => self.check_keyword(2, 2, "ue", TokenType::True),
+                        'y' => self.check_keyword(2, 4, "ping", TokenType::Typing),
+                        _ => TokenType::Identifier
+                    };
+                }
+
+                TokenType::Identifier
+            }
             'w' => self.check_keyword(1, 4, "hile", TokenType::While),
             'f' => {
                 if self.current_index - self.start_index > 1 {
@@ -292,4 +411,30 @@ impl<'a> Scanner<'a> {
         self.current_index += 1;
         return true;
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use san_common::keycodes::HID_KEY_STRINGS;
+    use crate::media::MEDIA_KEYS;
+    use crate::token::TokenType;
+    use super::Scanner;
+
+    fn token_type(source: &str) -> TokenType {
+        Scanner::new(source).scan_token().token_type
+    }
+
+    #[test]
+    fn keyboard_keys_stay_hid_keys() {
+        for name in HID_KEY_STRINGS {
+            assert_eq!(token_type(name), TokenType::HidKey, "{} is not lexed as a HID key", name);
+        }
+    }
+
+    #[test]
+    fn media_keys_have_names_of_their_own() {
+        for key in &MEDIA_KEYS {
+            assert_eq!(token_type(key.name), TokenType::MediaKey, "{} is not lexed as a media key", key.name);
+        }
+    }
+}
@@ -0,0 +1,125 @@
+use std::collections::{HashMap, HashSet};
+use san_common::chunk::OpCode;
+use san_common::value::{FunctionData, Value};
+use crate::optimizer::{decode, encode, Instruction};
+
+//drops global functions nothing can call, mostly the unused helpers of imported modules
+pub fn tree_shake(function: &FunctionData) -> FunctionData {
+    let mut definitions = HashMap::new();
+    let mut reads = HashSet::new();
+    scan(function, &mut definitions, &mut reads);
+
+    let mut used = HashSet::new();
+    let mut pending: Vec<String> = reads.into_iter().collect();
+    while let Some(name) = pending.pop() {
+        if !used.insert(name.clone()) {
+            continue;
+        }
+        for body in definitions.get(&name).cloned().unwrap_or_default() {
+            let mut body_reads = HashSet::new();
+            scan(&body, &mut HashMap::new(), &mut body_reads);
+            pending.extend(body_reads.into_iter().filter(|read| !used.contains(read)));
+        }
+    }
+
+    rewrite(function, &used)
+}
+
+//a function constant right before a global definition is a `fn` declaration, its body only counts once the name is used
+fn is_definition(instructions: &[Instruction], index: usize) -> Option<(String, FunctionData)> {
+    let (Some(Value::ValFunction(body)), Some(next)) = (&instructions[index].constant, instructions.get(index + 1)) else {
+        return None;
+    };
+    match (next.op, &next.constant) {
+        (OpCode::OpDefineGlobal(_), Some(Value::ValString(name))) => Some((name.clone(), body.clone())),
+        _ => None,
+    }
+}
+
+fn scan(function: &FunctionData, definitions: &mut HashMap<String, Vec<FunctionData>>, reads: &mut HashSet<String>) {
+    let instructions = decode(&function.chunk);
+    let mut index = 0;
+    while index < instructions.len() {
+        if let Some((name, body)) = is_definition(&instructions, index) {
+            definitions.entry(name).or_default().push(body);
+            index += 2;
+            continue;
+        }
+        let instruction = &instructions[index];
+        match (instruction.op, &instruction.constant) {
+            (OpCode::OpGetGlobal(_), Some(Value::ValString(name))) | (OpCode::OpSetGlobal(_), Some(Value::ValString(name))) => {
+                reads.insert(name.clone());
+            }
+            (_, Some(Value::ValFunction(nested))) => scan(nested, definitions, reads),
+            _ => {}
+        }
+        index += 1;
+    }
+}
+
+fn rewrite(function: &FunctionData, used: &HashSet<String>) -> FunctionData {
+    let instructions = decode(&function.chunk);
+    let mut kept = Vec::with_capacity(instructions.len());
+    let mut index = 0;
+    while index < instructions.len() {
+        if let Some((name, _)) = is_definition(&instructions, index) {
+            if !used.contains(&name) {
+                index += 2;
+                continue;
+            }
+        }
+        let mut instruction = instructions[index].clone();
+        if let Some(Value::ValFunction(nested)) = &instruction.constant {
+            instruction.constant = Some(Value::ValFunction(rewrite(nested, used)));
+        }
+        kept.push(instruction);
+        index += 1;
+    }
+
+    let mut shaken = function.clone();
+    shaken.chunk = encode(&kept);
+    shaken
+}
+
+#[cfg(test)]
+mod tests {
+    use san_common::chunk::OpCode;
+    use san_common::value::{FunctionData, Value};
+    use crate::optimizer::decode;
+    use crate::test_support::{compile, printed};
+    use super::tree_shake;
+
+    fn shaken(source: &str) -> FunctionData {
+        tree_shake(&compile(source).expect("source does not compile"))
+    }
+
+    fn defined_globals(function: &FunctionData) -> Vec<String> {
+        decode(&function.chunk)
+            .into_iter()
+            .filter_map(|instruction| match (instruction.op, instruction.constant) {
+                (OpCode::OpDefineGlobal(_), Some(Value::ValString(name))) => Some(name),
+                _ => None
+            })
+            .collect()
+    }
+
+    #[test]
+    fn keeps_functions_called_through_other_functions() {
+        let function = shaken("fn outer() { inner(); }\nfn inner() { print 1; }\nfn unused() { print 2; }\nouter();\n");
+        assert_eq!(defined_globals(&function), vec!["outer", "inner"]);
+
+        assert_eq!(printed(function), vec!["1"]);
+    }
+
+    #[test]
+    fn keeps_functions_stored_in_variables() {
+        let function = shaken("fn step() { print 1; }\nlet callback = step;\ncallback();\n");
+        assert_eq!(defined_globals(&function), vec!["step", "callback"]);
+    }
+
+    #[test]
+    fn drops_functions_only_unused_functions_call() {
+        let function = shaken("fn helper() { print 1; }\nfn unused() { helper(); }\nprint 2;\n");
+        assert!(defined_globals(&function).is_empty());
+    }
+}
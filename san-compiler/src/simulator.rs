@@ -0,0 +1,574 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::rc::Rc;
+use san_common::chunk::OpCode;
+use san_common::value::{FunctionData, Number, Value};
+use crate::layout::{alt_gr_code, shift_code, KeyboardLayout, Modifier};
+use crate::media::media_key_by_code;
+use crate::mouse::{DIGITIZER_MAX, SCROLL_MAX};
+use crate::natives::NATIVES;
+use crate::trace::{Trace, TraceEvent};
+use crate::typing::{Jitter, TypingProfile};
+
+const DEFAULT_STEP_LIMIT: usize = 10_000_000;
+
+#[derive(Clone)]
+pub enum SimValue {
+    Nil,
+    Bool(bool),
+    Number(Number),
+    String(String),
+    //sequence of chords, every chord is a set of keys pressed together
+    Keys(Vec<Vec<u8>>),
+    MouseButton(u8),
+    Function(Rc<FunctionData>),
+    Native(usize),
+}
+
+impl SimValue {
+    pub fn from_constant(value: &Value) -> Option<SimValue> {
+        match value {
+            Value::ValNil => Some(SimValue::Nil),
+            Value::ValBool(b) => Some(SimValue::Bool(*b)),
+            Value::ValNumber(n) => Some(SimValue::Number(*n)),
+            Value::ValString(s) => Some(SimValue::String(s.clone())),
+            Value::ValKey(codes) => Some(SimValue::Keys(vec![codes.clone()])),
+            Value::ValMouseButton(button) => Some(SimValue::MouseButton(*button)),
+            Value::ValFunction(function) => Some(SimValue::Function(Rc::new(function.clone()))),
+            _ => None
+        }
+    }
+
+    pub fn to_constant(&self) -> Option<Value> {
+        match self {
+            SimValue::Nil => Some(Value::ValNil),
+            SimValue::Bool(b) => Some(Value::ValBool(*b)),
+            SimValue::Number(n) => Some(Value::ValNumber(*n)),
+            SimValue::String(s) => Some(Value::ValString(s.clone())),
+            SimValue::Keys(chords) if chords.len() == 1 => Some(Value::ValKey(chords[0].clone())),
+            SimValue::MouseButton(button) => Some(Value::ValMouseButton(*button)),
+            SimValue::Function(function) => Some(Value::ValFunction(function.as_ref().clone())),
+            _ => None
+        }
+    }
+
+    pub fn is_falsey(&self) -> bool {
+        matches!(self, SimValue::Nil | SimValue::Bool(false))
+    }
+
+    pub fn equals(&self, other: &SimValue) -> bool {
+        match (self, other) {
+            (SimValue::Nil, SimValue::Nil) => true,
+            (SimValue::Bool(a), SimValue::Bool(b)) => a == b,
+            (SimValue::Number(a), SimValue::Number(b)) => a == b,
+            (SimValue::String(a), SimValue::String(b)) => a == b,
+            (SimValue::Keys(a), SimValue::Keys(b)) => a == b,
+            (SimValue::MouseButton(a), SimValue::MouseButton(b)) => a == b,
+            (SimValue::Function(a), SimValue::Function(b)) => Rc::ptr_eq(a, b),
+            (SimValue::Native(a), SimValue::Native(b)) => a == b,
+            _ => false
+        }
+    }
+
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            SimValue::Nil => "nil",
+            SimValue::Bool(_) => "bool",
+            SimValue::Number(_) => "number",
+            SimValue::String(_) => "string",
+            SimValue::Keys(_) => "keys",
+            SimValue::MouseButton(_) => "mouse button",
+            SimValue::Function(_) => "function",
+            SimValue::Native(_) => "native function",
+        }
+    }
+}
+
+impl fmt::Display for SimValue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SimValue::Nil => write!(f, "nil"),
+            SimValue::Bool(b) => write!(f, "{}", b),
+            SimValue::Number(n) => write!(f, "{}", n),
+            SimValue::String(s) => write!(f, "{}", s),
+            SimValue::Keys(chords) => {
+                let chords: Vec<String> = chords.iter()
+                    .map(|chord| chord.iter().map(|code| format!("0x{:02X}", code)).collect::<Vec<String>>().join("+"))
+                    .collect();
+                write!(f, "<keys {}>", chords.join(" | "))
+            }
+            SimValue::MouseButton(button) => write!(f, "<mouse button {}>", button),
+            SimValue::Function(function) => {
+                if function.name.is_empty() { write!(f, "<script>") } else { write!(f, "<fn {}>", function.name) }
+            }
+            SimValue::Native(index) => write!(f, "<native fn {}>", NATIVES[*index].name),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct SimError {
+    pub message: String,
+    pub line: usize,
+}
+
+impl fmt::Display for SimError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "[line {}] Runtime error: {}", self.line, self.message)
+    }
+}
+
+struct CallFrame {
+    function: Rc<FunctionData>,
+    ip: usize,
+    base: usize,
+}
+
+pub struct Simulator {
+    stack: Vec<SimValue>,
+    frames: Vec<CallFrame>,
+    globals: HashMap<String, SimValue>,
+    layout: &'static KeyboardLayout,
+    steps: usize,
+    step_limit: usize,
+    jitter: Jitter,
+    //keys pressed with key_down, they stay in every report until they are released
+    held: Vec<u8>,
+    pub trace: Trace,
+}
+
+impl Simulator {
+    pub fn new(layout: &'static KeyboardLayout) -> Simulator {
+        let mut simulator = Simulator {
+            stack: vec![],
+            frames: vec![],
+            globals: HashMap::new(),
+            layout,
+            steps: 0,
+            step_limit: DEFAULT_STEP_LIMIT,
+            jitter: Jitter::default(),
+            held: vec![],
+            trace: Trace::new(),
+        };
+
+        for (index, native) in NATIVES.iter().enumerate() {
+            simulator.globals.insert(native.name.to_string(), SimValue::Native(index));
+        }
+
+        simulator
+    }
+
+    pub fn set_step_limit(&mut self, step_limit: usize) {
+        self.step_limit = step_limit;
+    }
+
+    pub fn run(&mut self, function: FunctionData) -> Result<SimValue, SimError> {
+        self.call_value(SimValue::Function(Rc::new(function)), vec![])
+    }
+
+    pub fn call_value(&mut self, callee: SimValue, args: Vec<SimValue>) -> Result<SimValue, SimError> {
+        let base = self.stack.len();
+        let depth = self.frames.len();
+        let arg_count = args.len();
+        self.stack.push(callee);
+        self.stack.extend(args);
+
+        let result = self.call(arg_count).and_then(|_| {
+            if self.frames.len() > depth { self.execute(depth) } else { Ok(self.stack.pop().unwrap_or(SimValue::Nil)) }
+        });
+
+        if result.is_err() {
+            self.frames.truncate(depth);
+        }
+        self.stack.truncate(base);
+        result
+    }
+
+    fn execute(&mut self, depth: usize) -> Result<SimValue, SimError> {
+        loop {
+            self.steps += 1;
+            if self.steps > self.step_limit {
+                return Err(self.runtime_error(format!("Step limit of {} instructions exceeded", self.step_limit)));
+            }
+
+            let frame = self.frames.last_mut().expect("Simulator has no call frame!");
+            let op = frame.function.chunk.get_code(frame.ip).clone();
+            frame.ip += 1;
+
+            match op {
+                OpCode::OpConstant(index) => {
+                    let value = self.read_constant(index)?;
+                    self.stack.push(value);
+                }
+                OpCode::OpNil => self.stack.push(SimValue::Nil),
+                OpCode::OpTrue => self.stack.push(SimValue::Bool(true)),
+                OpCode::OpFalse => self.stack.push(SimValue::Bool(false)),
+                OpCode::OpPop => { self.pop(); }
+                OpCode::OpGetLocal(slot) => {
+                    let value = self.stack[self.base() + slot].clone();
+                    self.stack.push(value);
+                }
+                OpCode::OpSetLocal(slot) => {
+                    let base = self.base();
+                    self.stack[base + slot] = self.peek(0).clone();
+                }
+                OpCode::OpDefineGlobal(index) => {
+                    let name = self.read_name(index)?;
+                    let value = self.pop();
+                    self.globals.insert(name, value);
+                }
+                OpCode::OpGetGlobal(index) => {
+                    let name = self.read_name(index)?;
+                    match self.globals.get(&name) {
+                        Some(value) => {
+                            let value = value.clone();
+                            self.stack.push(value);
+                        }
+                        None => return Err(self.runtime_error(format!("Undefined variable '{}'", name))),
+                    }
+                }
+                OpCode::OpSetGlobal(index) => {
+                    let name = self.read_name(index)?;
+                    if !self.globals.contains_key(&name) {
+                        return Err(self.runtime_error(format!("Undefined variable '{}'", name)));
+                    }
+                    let value = self.peek(0).clone();
+                    self.globals.insert(name, value);
+                }
+                OpCode::OpEqual => {
+                    let b = self.pop();
+                    let a = self.pop();
+                    self.stack.push(SimValue::Bool(a.equals(&b)));
+                }
+                OpCode::OpGreater | OpCode::OpLess | OpCode::OpAdd | OpCode::OpSubtract
+                | OpCode::OpMultiply | OpCode::OpDivide | OpCode::OpPipe => {
+                    let b = self.pop();
+                    let a = self.pop();
+                    let value = binary_op(op, a, b).map_err(|message| self.runtime_error(message))?;
+                    self.stack.push(value);
+                }
+                OpCode::OpNot => {
+                    let value = self.pop();
+                    self.stack.push(SimValue::Bool(value.is_falsey()));
+                }
+                OpCode::OpNegate => {
+                    let value = self.pop();
+                    let value = unary_op(op, value).map_err(|message| self.runtime_error(message))?;
+                    self.stack.push(value);
+                }
+                OpCode::OpPrint => {
+                    let value = self.pop();
+                    self.trace.push(TraceEvent::Print(value.to_string()));
+                }
+                OpCode::OpJump(offset) => self.frame_mut().ip += offset,
+                OpCode::OpJumpIfFalse(offset) => {
+                    if self.peek(0).is_falsey() {
+                        self.frame_mut().ip += offset;
+                    }
+                }
+                OpCode::OpJumpIfTrue(offset) => {
+                    if !self.peek(0).is_falsey() {
+                        self.frame_mut().ip += offset;
+                    }
+                }
+                OpCode::OpLoop(offset) => self.frame_mut().ip -= offset,
+                OpCode::OpCall(arg_count) => self.call(arg_count)?,
+                OpCode::OpReturn => {
+                    let result = self.pop();
+                    let frame = self.frames.pop().expect("Simulator has no call frame!");
+                    self.stack.truncate(frame.base);
+                    if self.frames.len() <= depth {
+                        return Ok(result);
+                    }
+                    self.stack.push(result);
+                }
+                #[allow(unreachable_patterns)]
+                _ => return Err(self.runtime_error(format!("Unsupported instruction {:?}", op))),
+            }
+        }
+    }
+
+    fn call(&mut self, arg_count: usize) -> Result<(), SimError> {
+        let callee = self.peek(arg_count).clone();
+        match callee {
+            SimValue::Function(function) => {
+                if function.arity != arg_count {
+                    return Err(self.runtime_error(format!("Expected {} arguments but got {}", function.arity, arg_count)));
+                }
+                let base = self.stack.len() - arg_count - 1;
+                self.frames.push(CallFrame { function, ip: 0, base });
+                Ok(())
+            }
+            SimValue::Native(index) => {
+                let (name, arity) = (NATIVES[index].name, NATIVES[index].arity());
+                if arity != arg_count {
+                    return Err(self.runtime_error(format!("Native '{}' expects {} arguments but got {}", name, arity, arg_count)));
+                }
+                let args = self.stack.split_off(self.stack.len() - arg_count);
+                self.pop();
+                let result = self.call_native(name, args).map_err(|message| self.runtime_error(message))?;
+                self.stack.push(result);
+                Ok(())
+            }
+            other => Err(self.runtime_error(format!("Can only call functions, got {}", other.type_name()))),
+        }
+    }
+
+    fn call_native(&mut self, name: &str, args: Vec<SimValue>) -> Result<SimValue, String> {
+        match name {
+            "inject_keys" => {
+                for chord in expect_keys(&args[0])? {
+                    self.press(chord);
+                    self.release();
+                }
+            }
+            "inject_sequence" => {
+                //left out delays are nil and come from the typing profile
+                let delay = match &args[1] {
+                    SimValue::Nil => None,
+                    delay => Some(expect_millis(delay)?),
+                };
+                let hold = match &args[2] {
+                    SimValue::Nil => 0,
+                    hold => expect_millis(hold)?,
+                };
+                self.type_chords(expect_keys(&args[0])?, delay, hold);
+            }
+            "type_text" => {
+                let chords = match &args[0] {
+                    SimValue::String(text) => string_to_keys(self.layout, text)?,
+                    other => return Err(format!("Expected string but got {}", other.type_name())),
+                };
+                self.type_chords(&chords, None, 0);
+            }
+            "__typing_default" => {}
+            "__set_typing" => {
+                let profile = TypingProfile { speed: expect_millis(&args[0])?, jitter: expect_millis(&args[1])?, seed: expect_millis(&args[2])? };
+                self.jitter = Jitter::new(profile);
+            }
+            "string_to_keys" => {
+                let text = match &args[0] {
+                    SimValue::String(text) => text,
+                    other => return Err(format!("Expected string but got {}", other.type_name())),
+                };
+                return string_to_keys(self.layout, text).map(SimValue::Keys);
+            }
+            "sleep" => {
+                let ms = expect_millis(&args[0])?;
+                self.trace.delay(ms);
+            }
+            "mouse_move" => {
+                let x = expect_number(&args[0])?;
+                let y = expect_number(&args[1])?;
+                self.trace.push(TraceEvent::MouseMove(x as i64, y as i64));
+            }
+            "mouse_hold" => match &args[0] {
+                SimValue::MouseButton(button) => self.trace.push(TraceEvent::MouseDown(*button)),
+                other => return Err(format!("Expected mouse button but got {}", other.type_name())),
+            },
+            "mouse_up" => self.trace.push(TraceEvent::MouseUp),
+            "mouse_scroll" => {
+                let vertical = expect_range(&args[0], -SCROLL_MAX, SCROLL_MAX)?;
+                let horizontal = match &args[1] {
+                    SimValue::Nil => 0,
+                    horizontal => expect_range(horizontal, -SCROLL_MAX, SCROLL_MAX)?,
+                };
+                self.trace.push(TraceEvent::MouseScroll(vertical, horizontal));
+            }
+            "mouse_click" => {
+                let button = match &args[0] {
+                    SimValue::MouseButton(button) => *button,
+                    other => return Err(format!("Expected mouse button but got {}", other.type_name())),
+                };
+                let count = match &args[1] {
+                    SimValue::Nil => 1,
+                    count => expect_millis(count)?,
+                };
+                for _ in 0..count {
+                    self.trace.push(TraceEvent::MouseClick(button));
+                }
+            }
+            "mouse_move_to" => {
+                let x = expect_range(&args[0], 0.0, DIGITIZER_MAX)?;
+                let y = expect_range(&args[1], 0.0, DIGITIZER_MAX)?;
+                self.trace.push(TraceEvent::MouseMoveTo(x, y));
+            }
+            "inject_media" => {
+                let code = expect_number(&args[0])? as u32;
+                if media_key_by_code(code).is_none() {
+                    return Err(format!("Expected a media key but got {}", args[0]));
+                }
+                let times = match &args[1] {
+                    SimValue::Nil => 1,
+                    times => expect_millis(times)?,
+                };
+                for _ in 0..times {
+                    self.trace.push(TraceEvent::Media(code));
+                    let delay = match &args[2] {
+                        SimValue::Nil => self.jitter.next_delay(),
+                        delay => expect_millis(delay)?,
+                    };
+                    self.trace.delay(delay);
+                }
+            }
+            "key_down" => {
+                for code in expect_keys(&args[0])?.iter().flatten() {
+                    if !self.held.contains(code) {
+                        self.held.push(*code);
+                    }
+                }
+                self.trace.push(TraceEvent::KeyReport(self.held.clone()));
+            }
+            "key_up" => {
+                let keys = expect_keys(&args[0])?;
+                self.held.retain(|code| !keys.iter().flatten().any(|key| key == code));
+                self.trace.push(TraceEvent::KeyReport(self.held.clone()));
+            }
+            "release_all" => {
+                self.held.clear();
+                self.trace.push(TraceEvent::KeyReport(vec![]));
+            }
+            "__assert_fail" => return Err(format!("Assertion failed: {}", args[0])),
+            _ => return Err(format!("Native '{}' is not supported by the simulator", name)),
+        }
+
+        Ok(SimValue::Nil)
+    }
+
+    fn type_chords(&mut self, chords: &[Vec<u8>], delay: Option<u64>, hold: u64) {
+        for chord in chords {
+            self.press(chord);
+            self.trace.delay(hold);
+            self.release();
+            let delay = delay.unwrap_or_else(|| self.jitter.next_delay());
+            self.trace.delay(delay);
+        }
+    }
+
+    //a tap reports the chord together with the held keys, and releasing it goes back to only the held keys
+    fn press(&mut self, chord: &[u8]) {
+        let mut report = self.held.clone();
+        report.extend(chord.iter().filter(|code| !self.held.contains(code)));
+        self.trace.push(TraceEvent::KeyReport(report));
+    }
+
+    fn release(&mut self) {
+        self.trace.push(TraceEvent::KeyReport(self.held.clone()));
+    }
+
+    pub fn global(&self, name: &str) -> Option<&SimValue> {
+        self.globals.get(name)
+    }
+
+    fn read_constant(&self, index: usize) -> Result<SimValue, SimError> {
+        let constant = self.frame().function.chunk.get_constant(index).clone();
+        SimValue::from_constant(&constant).ok_or_else(|| self.runtime_error(String::from("Unsupported constant value")))
+    }
+
+    fn read_name(&self, index: usize) -> Result<String, SimError> {
+        match self.read_constant(index)? {
+            SimValue::String(name) => Ok(name),
+            _ => Err(self.runtime_error(String::from("Global name must be a string constant"))),
+        }
+    }
+
+    fn frame(&self) -> &CallFrame {
+        self.frames.last().expect("Simulator has no call frame!")
+    }
+
+    fn frame_mut(&mut self) -> &mut CallFrame {
+        self.frames.last_mut().expect("Simulator has no call frame!")
+    }
+
+    fn base(&self) -> usize {
+        self.frame().base
+    }
+
+    fn pop(&mut self) -> SimValue {
+        self.stack.pop().expect("Simulator stack underflow!")
+    }
+
+    fn peek(&self, distance: usize) -> &SimValue {
+        &self.stack[self.stack.len() - 1 - distance]
+    }
+
+    fn runtime_error(&self, message: String) -> SimError {
+        let line = match self.frames.last() {
+            Some(frame) if frame.ip > 0 => frame.function.chunk.get_line(frame.ip - 1),
+            _ => 0
+        };
+        SimError { message, line }
+    }
+}
+
+pub fn string_to_keys(layout: &KeyboardLayout, text: &str) -> Result<Vec<Vec<u8>>, String> {
+    text.chars().map(|c| match layout.char_to_key(c) {
+        Some((code, Modifier::None)) => Ok(vec![code]),
+        Some((code, Modifier::Shift)) => Ok(vec![shift_code(), code]),
+        Some((code, Modifier::AltGr)) => Ok(vec![alt_gr_code(), code]),
+        None => Err(format!("Character '{}' can't be typed with the '{}' layout", c, layout.name)),
+    }).collect()
+}
+
+pub fn binary_op(op: OpCode, a: SimValue, b: SimValue) -> Result<SimValue, String> {
+    match (op, a, b) {
+        (OpCode::OpAdd, SimValue::Number(a), SimValue::Number(b)) => Ok(SimValue::Number(a + b)),
+        (OpCode::OpAdd, SimValue::String(a), SimValue::String(b)) => Ok(SimValue::String(a + b.as_str())),
+        (OpCode::OpAdd, SimValue::Keys(mut a), SimValue::Keys(b)) if a.len() == 1 && b.len() == 1 => {
+            a[0].extend(b[0].iter());
+            Ok(SimValue::Keys(a))
+        }
+        (OpCode::OpAdd, _, _) => Err(String::from("Operands must be two numbers, two strings or two single keys")),
+        (OpCode::OpPipe, SimValue::Keys(mut a), SimValue::Keys(b)) => {
+            a.extend(b);
+            Ok(SimValue::Keys(a))
+        }
+        (OpCode::OpPipe, _, _) => Err(String::from("Operands must be key sequences")),
+        (OpCode::OpSubtract, SimValue::Number(a), SimValue::Number(b)) => Ok(SimValue::Number(a - b)),
+        (OpCode::OpMultiply, SimValue::Number(a), SimValue::Number(b)) => Ok(SimValue::Number(a * b)),
+        (OpCode::OpDivide, SimValue::Number(a), SimValue::Number(b)) => Ok(SimValue::Number(a / b)),
+        (OpCode::OpGreater, SimValue::Number(a), SimValue::Number(b)) => Ok(SimValue::Bool(a > b)),
+        (OpCode::OpLess, SimValue::Number(a), SimValue::Number(b)) => Ok(SimValue::Bool(a < b)),
+        (OpCode::OpEqual, a, b) => Ok(SimValue::Bool(a.equals(&b))),
+        _ => Err(String::from("Operands must be numbers")),
+    }
+}
+
+pub fn unary_op(op: OpCode, value: SimValue) -> Result<SimValue, String> {
+    match (op, value) {
+        (OpCode::OpNegate, SimValue::Number(n)) => Ok(SimValue::Number(-n)),
+        (OpCode::OpNegate, _) => Err(String::from("Operand must be a number")),
+        (OpCode::OpNot, value) => Ok(SimValue::Bool(value.is_falsey())),
+        _ => Err(String::from("Unsupported unary operation")),
+    }
+}
+
+fn expect_keys(value: &SimValue) -> Result<&Vec<Vec<u8>>, String> {
+    match value {
+        SimValue::Keys(chords) => Ok(chords),
+        other => Err(format!("Expected keys but got {}", other.type_name())),
+    }
+}
+
+fn expect_number(value: &SimValue) -> Result<Number, String> {
+    match value {
+        SimValue::Number(n) => Ok(*n),
+        other => Err(format!("Expected number but got {}", other.type_name())),
+    }
+}
+
+fn expect_millis(value: &SimValue) -> Result<u64, String> {
+    let ms = expect_number(value)?;
+    if ms < 0.0 {
+        return Err(format!("Expected a non-negative duration but got {}", ms));
+    }
+    Ok(ms as u64)
+}
+
+fn expect_range(value: &SimValue, min: Number, max: Number) -> Result<i64, String> {
+    let number = expect_number(value)?;
+    if number < min || number > max {
+        return Err(format!("Expected a number between {} and {} but got {}", min, max, number));
+    }
+    Ok(number as i64)
+}
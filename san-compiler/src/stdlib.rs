@@ -0,0 +1,54 @@
+use std::path::{Path, PathBuf};
+
+//imports starting with this are served from the sources below instead of the file system
+pub const STD_IMPORT: &str = "std/";
+
+//embedded so the library always matches the compiler it ships with
+const STD_MODULES: [(&str, &str); 3] = [
+    ("os", include_str!("../std/os.san")),
+    ("type", include_str!("../std/type.san")),
+    ("mouse", include_str!("../std/mouse.san")),
+];
+
+//`std/os` and `std/os.san` both resolve to the virtual path `std/os.san`
+pub fn std_path(import: &str) -> Option<PathBuf> {
+    let name = import.strip_prefix(STD_IMPORT)?;
+    let name = name.strip_suffix(".san").unwrap_or(name);
+    STD_MODULES
+        .iter()
+        .find(|(module, _)| *module == name)
+        .map(|(module, _)| PathBuf::from(format!("{}{}.san", STD_IMPORT, module)))
+}
+
+pub fn std_source(path: &Path) -> Option<&'static str> {
+    let name = path.to_str()?.strip_prefix(STD_IMPORT)?.strip_suffix(".san")?;
+    STD_MODULES.iter().find(|(module, _)| *module == name).map(|(_, source)| *source)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::test_support::{compile, printed, run, us};
+    use crate::trace::TraceEvent;
+
+    const RETRY: &str = "import \"std/type\" as type;\nlet tries = 0;\nfn attempt() {\n    tries = tries + 1;\n    print tries;\n    return tries == 2;\n}\n";
+
+    #[test]
+    fn type_then_pause_types_the_whole_text_and_then_waits() {
+        let trace = run(compile("import \"std/type\" as type;\ntype.type_then_pause(\"ab\", 500);\n").unwrap());
+        assert_eq!(trace.render_text(us()), "ab");
+        assert_eq!(trace.events.last(), Some(&TraceEvent::Delay(500)));
+    }
+
+    #[test]
+    fn retry_stops_after_the_first_success() {
+        let source = format!("{}print type.retry(attempt, 5, 100);\n", RETRY);
+        assert_eq!(printed(compile(&source).unwrap()), vec!["1", "2", "true"]);
+        assert_eq!(run(compile(&source).unwrap()).duration(), 100);
+    }
+
+    #[test]
+    fn retry_gives_up_after_the_attempts() {
+        let source = format!("{}print type.retry(attempt, 1, 100);\n", RETRY);
+        assert_eq!(printed(compile(&source).unwrap()), vec!["1", "false"]);
+    }
+}
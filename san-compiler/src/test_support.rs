@@ -0,0 +1,47 @@
+//helpers shared by the unit tests of the compiler passes
+use std::path::Path;
+use san_common::value::{FunctionData, FunctionType};
+use crate::compiler::Compiler;
+use crate::layout::{layout_by_name, KeyboardLayout};
+use crate::params::Params;
+use crate::simulator::Simulator;
+use crate::trace::{Trace, TraceEvent};
+
+//compiles without printing diagnostics, None when the source has errors
+pub fn compile(source: &str) -> Option<FunctionData> {
+    compile_with(source, Params::new())
+}
+
+pub fn compile_with(source: &str, params: Params) -> Option<FunctionData> {
+    let mut compiler = Compiler::new(source, FunctionType::Script);
+    compiler.set_quiet(true);
+    compiler.set_params(params);
+    compiler.compile()
+}
+
+//imports are resolved relative to the path
+pub fn compile_at(source: &str, path: &Path) -> Option<FunctionData> {
+    let mut compiler = Compiler::new(source, FunctionType::Script);
+    compiler.set_quiet(true);
+    compiler.set_path(path);
+    compiler.compile()
+}
+
+pub fn us() -> &'static KeyboardLayout {
+    layout_by_name("us").expect("US layout is always available")
+}
+
+//runs the program in the simulator, it must not fail
+pub fn run(function: FunctionData) -> Trace {
+    let mut simulator = Simulator::new(us());
+    simulator.run(function).expect("program fails in the simulator");
+    simulator.trace
+}
+
+//the values the program prints, in order
+pub fn printed(function: FunctionData) -> Vec<String> {
+    run(function).events.into_iter().filter_map(|event| match event {
+        TraceEvent::Print(value) => Some(value),
+        _ => None
+    }).collect()
+}
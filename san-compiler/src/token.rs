@@ -10,6 +10,7 @@ pub enum TokenType {
     LeftBrace,
     RightBrace,
     Comma,
+    Colon,
     Dot,
     Minus,
     Plus,
@@ -17,6 +18,9 @@ pub enum TokenType {
     Semicolon,
     Slash,
     Star,
+    Hash,
+    LeftBracket,
+    RightBracket,
 
     //one or two character tokens
     Bang,
@@ -27,6 +31,7 @@ pub enum TokenType {
     GreaterEqual,
     Less,
     LessEqual,
+    Arrow,
 
     //literals
     Identifier,
@@ -34,25 +39,41 @@ pub enum TokenType {
     Number,
     HidKey,
     MouseButton,
+    //consumer and system control keys, like MEDIA_VOLUME_UP
+    MediaKey,
 
     //keywords
     And,
+    Assert,
+    Const,
     Else,
     False,
     For,
     Fn,
+    Hold,
     If,
     Key,
     Loop,
     Match,
     Nil,
     Or,
+    Param,
     Print,
     Return,
+    Target,
+    Test,
     True,
+    Typing,
     Let,
+    Import,
+    As,
     While,
 
+    //trivia, only produced when the scanner keeps it
+    Whitespace,
+    Newline,
+    Comment,
+
     //misc
     Error(String),
     EOF,
@@ -78,6 +99,8 @@ pub struct Token {
     pub start_index: usize,
     pub length: usize,
     pub line: usize,
+    //id of the source file, 0 for the compiled file and higher ids for the files it imports
+    pub file: usize,
 }
 
 impl Token {
@@ -87,6 +110,7 @@ impl Token {
             start_index,
             length,
             line,
+            file: 0,
         }
     }
 
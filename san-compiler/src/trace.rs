@@ -0,0 +1,159 @@
+use std::fmt;
+use crate::layout::{is_modifier, is_shift, key_name, mouse_name, alt_gr_code, KeyboardLayout, Modifier, KEY_BACKSPACE, KEY_ENTER, KEY_ESCAPE, KEY_TAB};
+use crate::media::media_key_by_code;
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum TraceEvent {
+    //keys held down in a single HID report, an empty report releases everything
+    KeyReport(Vec<u8>),
+    Delay(u64),
+    MouseMove(i64, i64),
+    MouseDown(u8),
+    MouseUp,
+    //wheel steps, vertical then horizontal
+    MouseScroll(i64, i64),
+    //a press and release of the button
+    MouseClick(u8),
+    //absolute digitizer coordinates
+    MouseMoveTo(i64, i64),
+    //encoded like the media key values, see MediaKey::code
+    Media(u32),
+    Print(String),
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Trace {
+    pub events: Vec<TraceEvent>,
+}
+
+impl Trace {
+    pub fn new() -> Trace {
+        Trace { events: vec![] }
+    }
+
+    pub fn push(&mut self, event: TraceEvent) {
+        self.events.push(event);
+    }
+
+    pub fn delay(&mut self, ms: u64) {
+        if ms > 0 {
+            self.events.push(TraceEvent::Delay(ms));
+        }
+    }
+
+    pub fn duration(&self) -> u64 {
+        self.events.iter().map(|event| match event {
+            TraceEvent::Delay(ms) => *ms,
+            _ => 0
+        }).sum()
+    }
+
+    //renders the trace as the text and shortcuts the target would receive, e.g. `[GUI+R] cmd⏎`
+    pub fn render_text(&self, layout: &KeyboardLayout) -> String {
+        let mut text = String::new();
+        let mut pending_space = false;
+
+        for event in &self.events {
+            let rendered = match event {
+                TraceEvent::KeyReport(keys) if keys.is_empty() => continue,
+                TraceEvent::KeyReport(keys) => match Trace::render_chord(keys, layout) {
+                    Some(c) => {
+                        if pending_space && c != '\n' && c != ' ' {
+                            text.push(' ');
+                        }
+                        pending_space = false;
+                        match c {
+                            '\n' => text.push_str("⏎\n"),
+                            '\t' => text.push('⇥'),
+                            c => text.push(c),
+                        }
+                        continue;
+                    }
+                    None => format!("[{}]", Trace::chord_names(keys)),
+                },
+                TraceEvent::MouseMove(x, y) => format!("[mouse {},{}]", x, y),
+                TraceEvent::MouseDown(button) => format!("[mouse down {}]", Trace::button_name(*button)),
+                TraceEvent::MouseUp => String::from("[mouse up]"),
+                TraceEvent::MouseScroll(vertical, horizontal) => format!("[scroll {},{}]", vertical, horizontal),
+                TraceEvent::MouseClick(button) => format!("[click {}]", Trace::button_name(*button)),
+                TraceEvent::MouseMoveTo(x, y) => format!("[mouse to {},{}]", x, y),
+                TraceEvent::Media(code) => format!("[{}]", Trace::media_name(*code)),
+                TraceEvent::Delay(_) | TraceEvent::Print(_) => continue,
+            };
+
+            if !text.is_empty() && !text.ends_with(char::is_whitespace) {
+                text.push(' ');
+            }
+            text.push_str(rendered.as_str());
+            pending_space = true;
+        }
+
+        text
+    }
+
+    //the character a chord types, None for shortcuts
+    pub fn render_chord(keys: &[u8], layout: &KeyboardLayout) -> Option<char> {
+        let mut modifier = Modifier::None;
+        let mut key = None;
+
+        for code in keys {
+            if is_shift(*code) && modifier == Modifier::None {
+                modifier = Modifier::Shift;
+            } else if *code == alt_gr_code() && modifier == Modifier::None {
+                modifier = Modifier::AltGr;
+            } else if is_modifier(*code) || key.is_some() {
+                return None;
+            } else {
+                key = Some(*code);
+            }
+        }
+
+        match key? {
+            KEY_ESCAPE | KEY_BACKSPACE => None,
+            KEY_ENTER | KEY_TAB if modifier != Modifier::None => None,
+            code => layout.key_to_char(code, modifier)
+        }
+    }
+
+    pub fn chord_names(keys: &[u8]) -> String {
+        keys.iter()
+            .map(|code| key_name(*code).map(String::from).unwrap_or_else(|| format!("0x{:02X}", code)))
+            .collect::<Vec<String>>()
+            .join("+")
+    }
+
+    fn media_name(code: u32) -> String {
+        media_key_by_code(code).map(|key| key.name.to_string()).unwrap_or_else(|| format!("0x{:06X}", code))
+    }
+
+    fn button_name(button: u8) -> String {
+        mouse_name(button).map(String::from).unwrap_or_else(|| format!("0x{:02X}", button))
+    }
+}
+
+impl fmt::Display for TraceEvent {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TraceEvent::KeyReport(keys) if keys.is_empty() => write!(f, "release"),
+            TraceEvent::KeyReport(keys) => write!(f, "keys {}", Trace::chord_names(keys)),
+            TraceEvent::Delay(ms) => write!(f, "delay {}", ms),
+            TraceEvent::MouseMove(x, y) => write!(f, "mouse_move {} {}", x, y),
+            TraceEvent::MouseDown(button) => write!(f, "mouse_down {}", Trace::button_name(*button)),
+            TraceEvent::MouseUp => write!(f, "mouse_up"),
+            TraceEvent::MouseScroll(vertical, horizontal) => write!(f, "mouse_scroll {} {}", vertical, horizontal),
+            TraceEvent::MouseClick(button) => write!(f, "mouse_click {}", Trace::button_name(*button)),
+            TraceEvent::MouseMoveTo(x, y) => write!(f, "mouse_move_to {} {}", x, y),
+            TraceEvent::Media(code) => write!(f, "media {}", Trace::media_name(*code)),
+            TraceEvent::Print(value) => write!(f, "print {}", value),
+        }
+    }
+}
+
+impl fmt::Display for Trace {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for event in &self.events {
+            writeln!(f, "{}", event)?;
+        }
+        Ok(())
+    }
+}
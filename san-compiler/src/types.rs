@@ -0,0 +1,186 @@
+use std::fmt;
+use std::sync::Arc;
+use san_common::value::Value;
+use crate::token::TokenType;
+
+//static types of SanScript values, Any is used wherever a type is neither annotated nor inferable
+#[derive(Clone, Debug, PartialEq)]
+pub enum Type {
+    Any,
+    Nil,
+    Bool,
+    Number,
+    String,
+    Keys,
+    MouseButton,
+    MediaKey,
+    Function(Option<Arc<Signature>>),
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Signature {
+    pub name: String,
+    pub params: Vec<Type>,
+    pub returns: Type,
+    //number of trailing params that may be left out
+    pub optional: usize,
+}
+
+impl Type {
+    pub fn from_name(name: &str) -> Option<Type> {
+        match name {
+            "Any" => Some(Type::Any),
+            "Nil" => Some(Type::Nil),
+            "Bool" => Some(Type::Bool),
+            "Number" => Some(Type::Number),
+            "String" => Some(Type::String),
+            "Keys" => Some(Type::Keys),
+            "MouseButton" => Some(Type::MouseButton),
+            "MediaKey" => Some(Type::MediaKey),
+            "Fn" => Some(Type::Function(None)),
+            _ => None
+        }
+    }
+
+    pub fn of_value(value: &Value) -> Type {
+        match value {
+            Value::ValNil => Type::Nil,
+            Value::ValBool(_) => Type::Bool,
+            Value::ValNumber(_) => Type::Number,
+            Value::ValString(_) => Type::String,
+            Value::ValKey(_) => Type::Keys,
+            Value::ValMouseButton(_) => Type::MouseButton,
+            Value::ValFunction(_) => Type::Function(None),
+            _ => Type::Any,
+        }
+    }
+
+    //whether a value of type `actual` can be used where `self` is expected
+    pub fn accepts(&self, actual: &Type) -> bool {
+        match (self, actual) {
+            (Type::Any, _) | (_, Type::Any) => true,
+            (Type::Function(_), Type::Function(_)) => true,
+            (expected, actual) => expected == actual,
+        }
+    }
+
+    //the type of a value that is either of the two, used for `and` and `or`
+    pub fn join(&self, other: &Type) -> Type {
+        match (self, other) {
+            (a, b) if a == b => a.clone(),
+            (Type::Function(_), Type::Function(_)) => Type::Function(None),
+            _ => Type::Any,
+        }
+    }
+}
+
+impl fmt::Display for Type {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Type::Any => write!(f, "Any"),
+            Type::Nil => write!(f, "Nil"),
+            Type::Bool => write!(f, "Bool"),
+            Type::Number => write!(f, "Number"),
+            Type::String => write!(f, "String"),
+            Type::Keys => write!(f, "Keys"),
+            Type::MouseButton => write!(f, "MouseButton"),
+            Type::MediaKey => write!(f, "MediaKey"),
+            Type::Function(None) => write!(f, "Fn"),
+            Type::Function(Some(signature)) => write!(f, "{}", signature),
+        }
+    }
+}
+
+impl fmt::Display for Signature {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let required = self.params.len() - self.optional;
+        let params: Vec<String> = self.params
+            .iter()
+            .enumerate()
+            .map(|(index, param)| if index < required { param.to_string() } else { format!("{}?", param) })
+            .collect();
+        write!(f, "fn({}) -> {}", params.join(", "), self.returns)
+    }
+}
+
+pub fn binary_type(operator: &TokenType, a: &Type, b: &Type) -> Result<Type, String> {
+    let numbers = Type::Number.accepts(a) && Type::Number.accepts(b);
+    match operator {
+        TokenType::Plus => match (a, b) {
+            (Type::Number, Type::Number) | (Type::String, Type::String) | (Type::Keys, Type::Keys) | (Type::Any, Type::Any) => Ok(a.clone()),
+            (Type::Any, t) | (t, Type::Any) if matches!(t, Type::Number | Type::String | Type::Keys) => Ok(t.clone()),
+            _ => Err(format!("Operands of '+' must be two numbers, two strings or two keys, found {} and {}", a, b)),
+        },
+        TokenType::Pipe => {
+            if Type::Keys.accepts(a) && Type::Keys.accepts(b) {
+                Ok(Type::Keys)
+            } else {
+                Err(format!("Operands of '|' must be keys, found {} and {}", a, b))
+            }
+        }
+        TokenType::Minus | TokenType::Star | TokenType::Slash if numbers => Ok(Type::Number),
+        TokenType::Greater | TokenType::GreaterEqual | TokenType::Less | TokenType::LessEqual if numbers => Ok(Type::Bool),
+        TokenType::EqualEqual | TokenType::BangEqual => Ok(Type::Bool),
+        _ => Err(format!("Operands of '{}' must be numbers, found {} and {}", operator_lexeme(operator), a, b)),
+    }
+}
+
+fn operator_lexeme(operator: &TokenType) -> &'static str {
+    match operator {
+        TokenType::Minus => "-",
+        TokenType::Star => "*",
+        TokenType::Slash => "/",
+        TokenType::Greater => ">",
+        TokenType::GreaterEqual => ">=",
+        TokenType::Less => "<",
+        TokenType::LessEqual => "<=",
+        _ => "?",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::test_support::compile;
+    use crate::token::TokenType;
+    use super::{binary_type, Signature, Type};
+
+    fn compiles(source: &str) -> bool {
+        compile(source).is_some()
+    }
+
+    #[test]
+    fn plus_rejects_mouse_buttons_and_keys() {
+        let error = binary_type(&TokenType::Plus, &Type::MouseButton, &Type::Keys).unwrap_err();
+        assert_eq!(error, "Operands of '+' must be two numbers, two strings or two keys, found MouseButton and Keys");
+        assert!(!compiles("let combo = LEFT_CLICK + ENTER;\n"));
+    }
+
+    #[test]
+    fn any_operands_take_the_known_type() {
+        assert_eq!(binary_type(&TokenType::Plus, &Type::Any, &Type::Keys), Ok(Type::Keys));
+        assert_eq!(binary_type(&TokenType::Pipe, &Type::Keys, &Type::Any), Ok(Type::Keys));
+        assert_eq!(binary_type(&TokenType::Less, &Type::Any, &Type::Number), Ok(Type::Bool));
+        assert!(binary_type(&TokenType::Minus, &Type::String, &Type::Number).is_err());
+    }
+
+    #[test]
+    fn annotations_are_checked_against_initializers() {
+        assert!(compiles("let count: Number = 3;\nprint count;\n"));
+        assert!(!compiles("let count: Number = \"three\";\nprint count;\n"));
+        assert!(!compiles("fn press(keys: Keys) { inject_keys(keys); }\npress(LEFT_CLICK);\n"));
+    }
+
+    #[test]
+    fn unannotated_variables_take_values_of_another_type() {
+        assert!(compiles("let x = 1;\nx = \"s\";\nprint x;\n"));
+        assert!(compiles("fn f() {\n    let k = ENTER;\n    k = 5;\n    print k;\n}\nf();\n"));
+        assert!(!compiles("let x: Number = 1;\nx = \"s\";\nprint x;\n"));
+        assert!(!compiles("fn f(count: Number) {\n    count = ENTER;\n}\nf(1);\n"));
+    }
+
+    #[test]
+    fn signatures_mark_optional_parameters() {
+        let signature = Signature { name: String::from("f"), params: vec![Type::Keys, Type::Number], returns: Type::Nil, optional: 1 };
+        assert_eq!(signature.to_string(), "fn(Keys, Number?) -> Nil");
+    }
+}
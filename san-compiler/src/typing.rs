@@ -0,0 +1,75 @@
+//delay after every key when a payload sets no typing profile
+pub const DEFAULT_SPEED: u64 = 30;
+pub const TYPING_SETTINGS: [&str; 3] = ["speed", "jitter", "seed"];
+
+//how `type_text` and `inject_sequence` without delays type, set with `typing { ... }` or [typing] in config.toml
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct TypingProfile {
+    //delay after every key in ms
+    pub speed: u64,
+    //the delay varies by up to this many ms in both directions
+    pub jitter: u64,
+    pub seed: u64,
+}
+
+impl Default for TypingProfile {
+    fn default() -> Self {
+        TypingProfile { speed: DEFAULT_SPEED, jitter: 0, seed: 0 }
+    }
+}
+
+impl TypingProfile {
+    pub fn set(&mut self, setting: &str, value: u64) -> Result<(), String> {
+        match setting {
+            "speed" => self.speed = value,
+            "jitter" => self.jitter = value,
+            "seed" => self.seed = value,
+            _ => return Err(format!("Unknown typing setting '{}', expected one of {}", setting, TYPING_SETTINGS.join(", "))),
+        }
+        Ok(())
+    }
+
+    pub fn min_delay(&self) -> u64 {
+        self.speed.saturating_sub(self.jitter)
+    }
+
+    pub fn max_delay(&self) -> u64 {
+        self.speed + self.jitter
+    }
+}
+
+//delays of a profile drawn from splitmix64, which is small enough for SanVM to implement the same way,
+//so a seeded payload types with the same delays on the device as in the simulator
+#[derive(Clone, Debug)]
+pub struct Jitter {
+    profile: TypingProfile,
+    state: u64,
+}
+
+impl Jitter {
+    pub fn new(profile: TypingProfile) -> Jitter {
+        Jitter { profile, state: profile.seed }
+    }
+
+    pub fn next_delay(&mut self) -> u64 {
+        if self.profile.jitter == 0 {
+            return self.profile.speed;
+        }
+        let offset = self.next() % (2 * self.profile.jitter + 1);
+        (self.profile.speed + offset).saturating_sub(self.profile.jitter)
+    }
+
+    fn next(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+}
+
+impl Default for Jitter {
+    fn default() -> Self {
+        Jitter::new(TypingProfile::default())
+    }
+}
@@ -0,0 +1,90 @@
+use std::env;
+use std::fs;
+use std::path::Path;
+use san_common::value::{FunctionData, FunctionType};
+use san_compiler::backend::{arduino_sketch, check_lowerable, circuitpython_script};
+use san_compiler::compiler::Compiler;
+use san_compiler::layout::layout_by_name;
+use san_compiler::optimizer::optimize;
+use san_compiler::simulator::Simulator;
+use san_compiler::trace::Trace;
+
+//golden files live next to their payload, run with SANC_BLESS=1 to rewrite them
+const GOLDEN_DIR: &str = "tests/backend";
+
+fn compile(source: &str) -> FunctionData {
+    let mut compiler = Compiler::new(source, FunctionType::Script);
+    compiler.set_quiet(true);
+    optimize(&compiler.compile().unwrap_or_else(|| panic!("payload does not compile:\n{}", source)))
+}
+
+fn run(function: FunctionData) -> Trace {
+    let mut simulator = Simulator::new(layout_by_name("us").unwrap());
+    simulator.run(function).unwrap_or_else(|e| panic!("payload failed with {}", e));
+    simulator.trace
+}
+
+fn assert_golden(path: &Path, generated: String) {
+    if env::var("SANC_BLESS").is_ok() {
+        fs::write(path, generated).unwrap();
+        return;
+    }
+    let expected = fs::read_to_string(path).unwrap_or_else(|e| panic!("missing golden file {}: {}", path.display(), e));
+    assert_eq!(generated, expected, "{} is out of date", path.display());
+}
+
+#[test]
+fn generates_golden_files() {
+    let layout = layout_by_name("us").unwrap();
+    let mut payloads: Vec<_> = fs::read_dir(GOLDEN_DIR).unwrap()
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.extension().map_or(false, |extension| extension == "san"))
+        .collect();
+    payloads.sort();
+    assert!(!payloads.is_empty(), "no payloads in {}", GOLDEN_DIR);
+
+    for payload in payloads {
+        let source = fs::read_to_string(&payload).unwrap();
+        let function = compile(source.as_str());
+        assert_eq!(check_lowerable(&function), vec![], "{} can't be lowered", payload.display());
+        let trace = run(function);
+        let name = payload.file_name().unwrap().to_string_lossy().to_string();
+
+        assert_golden(&payload.with_extension("ino"), arduino_sketch(&trace, layout, name.as_str()).unwrap());
+        assert_golden(&payload.with_extension("py"), circuitpython_script(&trace, layout, name.as_str()).unwrap());
+    }
+}
+
+#[test]
+fn lowers_folded_branches_and_calls() {
+    let function = compile("fn twice() {\n    inject_keys(string_to_keys(\"a\"));\n    inject_keys(string_to_keys(\"a\"));\n}\nif (1 < 2) {\n    twice();\n}\n");
+    assert_eq!(check_lowerable(&function), vec![]);
+    let script = circuitpython_script(&run(function), layout_by_name("us").unwrap(), "twice.san").unwrap();
+    assert_eq!(script.matches("keyboard.press(0x04)  # 'a'").count(), 2);
+    assert!(!script.contains("mouse"));
+}
+
+#[test]
+fn reports_constructs_it_cannot_lower() {
+    let function = compile("let count = 0;\nwhile (count < 3) {\n    count = count + 1;\n}\nif (count == 3) {\n    sleep(10);\n}\nfor (let i = 0; i < 2; i = i + 1) {\n    sleep(i);\n}\n");
+    let errors: Vec<String> = check_lowerable(&function).iter().map(|error| error.to_string()).collect();
+    assert_eq!(
+        errors,
+        vec![
+            "line 2: Loops can't be lowered",
+            "line 5: Conditions that aren't known at compile time can't be lowered",
+            "line 8: Loops can't be lowered",
+        ]
+    );
+}
+
+#[test]
+fn releases_held_keys_when_a_hold_is_left_early() {
+    let function = compile("fn shout() {\n    hold SHIFT {\n        inject_keys(A);\n        if (1 < 2) {\n            return;\n        }\n        inject_keys(B);\n    }\n}\nshout();\ninject_keys(C);\n");
+    assert_eq!(check_lowerable(&function), vec![]);
+    let script = circuitpython_script(&run(function), layout_by_name("us").unwrap(), "shout.san").unwrap();
+    let released = script.find("keyboard.release_all()").expect("the held SHIFT is never released");
+    let pressed = script.find("keyboard.press(0x06)").expect("C is never pressed");
+    assert!(released < pressed, "C is typed while SHIFT is still held:\n{}", script);
+    assert!(!script.contains("keyboard.press(0x05)"));
+}
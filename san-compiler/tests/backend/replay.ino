@@ -0,0 +1,52 @@
+// Generated by sanc from replay.san, do not edit
+#include <Keyboard.h>
+#include <Mouse.h>
+
+// Keyboard.h takes modifiers as 128 + their bit and any other key as 136 + its HID usage id
+void press(uint8_t code) {
+  if (code >= 0xE0 && code <= 0xE7) {
+    Keyboard.press(128 + code - 0xE0);
+  } else {
+    Keyboard.press(136 + code);
+  }
+}
+
+void release(uint8_t code) {
+  if (code >= 0xE0 && code <= 0xE7) {
+    Keyboard.release(128 + code - 0xE0);
+  } else {
+    Keyboard.release(136 + code);
+  }
+}
+
+// Mouse.move moves at most 127 on each axis
+void moveMouse(long x, long y) {
+  while (x != 0 || y != 0) {
+    long stepX = constrain(x, -127, 127);
+    long stepY = constrain(y, -127, 127);
+    Mouse.move(stepX, stepY, 0);
+    x -= stepX;
+    y -= stepY;
+  }
+}
+
+void setup() {
+  Keyboard.begin();
+  Mouse.begin();
+  Serial.begin(9600);
+  press(0x0B); // 'h'
+  Keyboard.releaseAll();
+  delay(30);
+  press(0x0C); // 'i'
+  Keyboard.releaseAll();
+  delay(530);
+  moveMouse(300, -20);
+  Mouse.press(MOUSE_LEFT);
+  moveMouse(0, 100);
+  Mouse.release(MOUSE_LEFT);
+  Serial.println("done");
+  Keyboard.end();
+}
+
+void loop() {
+}
@@ -0,0 +1,20 @@
+# Generated by sanc from replay.san, do not edit
+import time
+import usb_hid
+from adafruit_hid.keyboard import Keyboard
+from adafruit_hid.mouse import Mouse
+
+keyboard = Keyboard(usb_hid.devices)
+mouse = Mouse(usb_hid.devices)
+
+keyboard.press(0x0B)  # 'h'
+keyboard.release_all()
+time.sleep(0.03)
+keyboard.press(0x0C)  # 'i'
+keyboard.release_all()
+time.sleep(0.53)
+mouse.move(x=300, y=-20)
+mouse.press(Mouse.LEFT_BUTTON)
+mouse.move(x=0, y=100)
+mouse.release(Mouse.LEFT_BUTTON)
+print("done")
@@ -0,0 +1,348 @@
+use std::collections::HashMap;
+use san_common::value::FunctionType;
+use san_compiler::compiler::Compiler;
+use san_compiler::ducky::{ducky_key, translate};
+use san_compiler::layout::{key_code, layout_by_name, KeyboardLayout};
+use san_compiler::simulator::{string_to_keys, Simulator};
+use san_compiler::trace::{Trace, TraceEvent};
+
+//a reference interpreter of the DuckyScript the translator supports, playing the script the way a Ducky would
+struct Ducky<'a> {
+    lines: Vec<&'a str>,
+    layout: &'static KeyboardLayout,
+    variables: HashMap<String, i64>,
+    functions: HashMap<String, (usize, usize)>,
+    default_delay: u64,
+    string_delay: Option<u64>,
+    defines: Vec<(String, String)>,
+    trace: Trace,
+}
+
+impl<'a> Ducky<'a> {
+    fn play(script: &'a str) -> Trace {
+        let mut ducky = Ducky {
+            lines: script.lines().map(str::trim_start).collect(),
+            layout: layout_by_name("us").unwrap(),
+            variables: HashMap::new(),
+            functions: HashMap::new(),
+            default_delay: 0,
+            string_delay: None,
+            defines: vec![],
+            trace: Trace::new(),
+        };
+        let end = ducky.lines.len();
+        ducky.run(0, end);
+        ducky.trace
+    }
+
+    fn run(&mut self, start: usize, end: usize) {
+        let mut index = start;
+        let mut last = None;
+        while index < end {
+            let line = self.expand(self.lines[index]);
+            let (command, argument) = line.split_once(' ').unwrap_or((line.as_str(), ""));
+            match command {
+                "IF" => {
+                    let branches = self.branches(index);
+                    let taken = branches.windows(2).find(|branch| match self.lines[branch[0]].trim() {
+                        "ELSE" => true,
+                        header => self.eval(header.trim_start_matches("ELSE").trim().trim_start_matches("IF").trim_end_matches("THEN")) != 0,
+                    });
+                    if let Some(branch) = taken {
+                        self.run(branch[0] + 1, branch[1]);
+                    }
+                    index = *branches.last().unwrap() + 1;
+                    continue;
+                }
+                "WHILE" => {
+                    let close = *self.branches(index).last().unwrap();
+                    while self.eval(argument) != 0 {
+                        self.run(index + 1, close);
+                    }
+                    index = close + 1;
+                    continue;
+                }
+                "FUNCTION" => {
+                    let close = *self.branches(index).last().unwrap();
+                    self.functions.insert(argument.trim().trim_end_matches("()").to_string(), (index + 1, close));
+                    index = close + 1;
+                    continue;
+                }
+                "REPEAT" => {
+                    let previous: usize = last.expect("REPEAT without a command");
+                    for _ in 0..argument.trim().parse::<usize>().unwrap() {
+                        self.run(previous, previous + 1);
+                    }
+                }
+                _ => {
+                    self.command(line.as_str());
+                    last = Some(index);
+                }
+            }
+            index += 1;
+        }
+    }
+
+    fn command(&mut self, line: &str) {
+        let (command, argument) = line.split_once(' ').unwrap_or((line, ""));
+        match command {
+            "" | "REM" => (),
+            "DEFINE" => {
+                let (name, value) = argument.split_once(' ').unwrap();
+                self.defines.push((name.to_string(), value.to_string()));
+            }
+            "DEFAULT_DELAY" | "DEFAULTDELAY" => self.default_delay = argument.trim().parse().unwrap(),
+            "STRING_DELAY" | "STRINGDELAY" => self.string_delay = Some(argument.trim().parse().unwrap()),
+            "DELAY" => {
+                let delay = self.eval(argument);
+                self.trace.delay(delay as u64);
+            }
+            "STRING" | "STRINGLN" => {
+                let mut chords = string_to_keys(self.layout, argument).unwrap();
+                if command == "STRINGLN" {
+                    chords.push(vec![key_code("ENTER").unwrap()]);
+                }
+                let delay = self.string_delay.take();
+                for chord in chords {
+                    self.press(chord);
+                    self.trace.delay(delay.unwrap_or(0));
+                }
+                self.trace.delay(self.default_delay);
+            }
+            "VAR" => self.assign(argument),
+            _ if command.starts_with('$') => self.assign(line),
+            _ if line.trim_end().ends_with("()") => {
+                let (start, end) = self.functions[line.trim_end().trim_end_matches("()")];
+                self.run(start, end);
+            }
+            _ => {
+                let chord = line
+                    .split(|c: char| c.is_whitespace() || c == '-')
+                    .filter(|name| !name.is_empty())
+                    .map(|name| key_code(ducky_key(name).unwrap().as_str()).unwrap())
+                    .collect();
+                self.press(chord);
+                self.trace.delay(self.default_delay);
+            }
+        }
+    }
+
+    fn press(&mut self, chord: Vec<u8>) {
+        self.trace.push(TraceEvent::KeyReport(chord));
+        self.trace.push(TraceEvent::KeyReport(vec![]));
+    }
+
+    fn assign(&mut self, assignment: &str) {
+        let (name, value) = assignment.split_once('=').unwrap();
+        let value = self.eval(value);
+        self.variables.insert(name.trim().to_string(), value);
+    }
+
+    fn expand(&self, line: &str) -> String {
+        if line.starts_with("DEFINE ") {
+            return line.to_string();
+        }
+        self.defines.iter().fold(line.to_string(), |line, (name, value)| line.replace(name.as_str(), value.as_str()))
+    }
+
+    //offsets of the opening line, every ELSE of the block at its depth and the closing line
+    fn branches(&self, start: usize) -> Vec<usize> {
+        let mut branches = vec![start];
+        let mut depth = 0;
+        for (index, line) in self.lines.iter().enumerate().skip(start) {
+            let command = line.split_whitespace().next().unwrap_or("");
+            match command {
+                "IF" | "WHILE" | "FUNCTION" => depth += 1,
+                "END_IF" | "END_WHILE" | "END_FUNCTION" => {
+                    depth -= 1;
+                    if depth == 0 {
+                        branches.push(index);
+                        return branches;
+                    }
+                }
+                "ELSE" if depth == 1 => branches.push(index),
+                _ => (),
+            }
+        }
+        panic!("unterminated block at line {}", start + 1);
+    }
+
+    fn eval(&self, expression: &str) -> i64 {
+        let tokens = Ducky::tokenize(expression);
+        let mut position = 0;
+        let value = self.binary(&tokens, &mut position, 0);
+        assert_eq!(position, tokens.len(), "unexpected tokens in '{}'", expression);
+        value
+    }
+
+    fn tokenize(expression: &str) -> Vec<String> {
+        let mut tokens = vec![];
+        let mut chars = expression.chars().peekable();
+        while let Some(c) = chars.next() {
+            let mut token = c.to_string();
+            if c.is_whitespace() {
+                continue;
+            }
+            if c.is_ascii_alphanumeric() || c == '$' || c == '_' {
+                while let Some(next) = chars.peek().filter(|next| next.is_ascii_alphanumeric() || **next == '_') {
+                    token.push(*next);
+                    chars.next();
+                }
+            } else if let Some(next) = chars.peek().filter(|next| ['=', '&', '|'].contains(*next)) {
+                token.push(*next);
+                chars.next();
+            }
+            tokens.push(token);
+        }
+        tokens
+    }
+
+    fn binary(&self, tokens: &[String], position: &mut usize, min_precedence: u8) -> i64 {
+        let mut left = self.unary(tokens, position);
+        while let Some(operator) = tokens.get(*position) {
+            let precedence = match operator.as_str() {
+                "||" => 1,
+                "&&" => 2,
+                "==" | "!=" => 3,
+                "<" | ">" | "<=" | ">=" => 4,
+                "+" | "-" => 5,
+                "*" | "/" => 6,
+                _ => break,
+            };
+            if precedence <= min_precedence {
+                break;
+            }
+            *position += 1;
+            let right = self.binary(tokens, position, precedence);
+            left = match operator.as_str() {
+                "||" => (left != 0 || right != 0) as i64,
+                "&&" => (left != 0 && right != 0) as i64,
+                "==" => (left == right) as i64,
+                "!=" => (left != right) as i64,
+                "<" => (left < right) as i64,
+                ">" => (left > right) as i64,
+                "<=" => (left <= right) as i64,
+                ">=" => (left >= right) as i64,
+                "+" => left + right,
+                "-" => left - right,
+                "*" => left * right,
+                _ => left / right,
+            };
+        }
+        left
+    }
+
+    fn unary(&self, tokens: &[String], position: &mut usize) -> i64 {
+        let token = tokens[*position].as_str();
+        *position += 1;
+        match token {
+            "(" => {
+                let value = self.binary(tokens, position, 0);
+                *position += 1;
+                value
+            }
+            "!" => (self.unary(tokens, position) == 0) as i64,
+            "-" => -self.unary(tokens, position),
+            "TRUE" => 1,
+            "FALSE" => 0,
+            variable if variable.starts_with('$') => self.variables[variable],
+            number => number.parse().unwrap(),
+        }
+    }
+}
+
+fn simulate(source: &str) -> Trace {
+    let mut compiler = Compiler::new(source, FunctionType::Script);
+    compiler.set_quiet(true);
+    let function = compiler.compile().unwrap_or_else(|| panic!("translation does not compile:\n{}", source));
+    let mut simulator = Simulator::new(layout_by_name("us").unwrap());
+    simulator.run(function).unwrap_or_else(|e| panic!("translation failed with {}:\n{}", e, source));
+    simulator.trace
+}
+
+fn assert_same_trace(script: &str) {
+    let translation = translate(script);
+    assert!(translation.unsupported.is_empty(), "unsupported lines: {:?}", translation.unsupported);
+    assert_eq!(simulate(translation.source.as_str()), Ducky::play(script), "translation:\n{}", translation.source);
+}
+
+#[test]
+fn translates_keystroke_commands() {
+    assert_same_trace(
+        "REM open a terminal and say hello
+DEFAULT_DELAY 100
+CTRL ALT t
+DELAY 1000
+STRINGLN echo hello
+STRING Hello World!
+ENTER
+REPEAT 2
+GUI r
+",
+    );
+}
+
+#[test]
+fn translates_defines_and_string_delay() {
+    assert_same_trace(
+        "DEFINE #URL https://example.com
+GUI r
+DELAY 500
+STRING_DELAY 20
+STRINGLN chrome #URL
+STRING done
+",
+    );
+}
+
+#[test]
+fn translates_variables_and_control_flow() {
+    assert_same_trace(
+        "VAR $count = 0
+FUNCTION open_terminal()
+    CTRL-ALT t
+    DELAY 200
+END_FUNCTION
+WHILE ($count < 3)
+    open_terminal()
+    IF ($count == 1) THEN
+        STRING one
+    ELSE IF ($count == 2) THEN
+        STRING two
+    ELSE
+        STRING zero
+    END_IF
+    $count = ($count + 1)
+END_WHILE
+IF (($count == 3) && TRUE) THEN
+    STRINGLN exit
+END_IF
+",
+    );
+}
+
+#[test]
+fn writes_idiomatic_sanscript() {
+    let translation = translate("REM run\nGUI r\nDELAY 500\nSTRINGLN notepad\nWHILE TRUE\n    ENTER\nEND_WHILE\n");
+    assert_eq!(
+        translation.source,
+        "// run
+inject_keys(GUI + R);
+sleep(500);
+inject_keys(string_to_keys(\"notepad\") | ENTER);
+while (true) {
+    inject_keys(ENTER);
+}
+"
+    );
+}
+
+#[test]
+fn reports_untranslatable_lines() {
+    let translation = translate("ATTACKMODE HID\nSTRING ok\nVAR $rest = 5 % 2\nSTRING say \"hi\"\nIF TRUE THEN\n");
+    let lines: Vec<usize> = translation.unsupported.iter().map(|unsupported| unsupported.line).collect();
+    assert_eq!(lines, vec![1, 3, 4, 5]);
+    assert_eq!(translation.unsupported[0].message, "Unknown command or key 'ATTACKMODE'");
+    assert!(translation.source.contains("// untranslated: ATTACKMODE HID"));
+    assert_eq!(simulate(translation.source.as_str()), Ducky::play("STRING ok"));
+}
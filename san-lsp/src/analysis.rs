@@ -0,0 +1,191 @@
+use san_common::keycodes::{hid_string_to_code, mouse_string_to_code};
+use san_compiler::media::media_key;
+use san_compiler::scanner::Scanner;
+use san_compiler::natives::native_by_name;
+use san_compiler::token::{Token, TokenType};
+
+//maps char offsets of the scanner onto zero based LSP lines and columns
+//offsets are in chars like token indexes, columns are in UTF-16 code units like LSP positions
+pub struct LineIndex {
+    line_starts: Vec<usize>,
+    //UTF-16 length of every char
+    widths: Vec<usize>,
+}
+
+impl LineIndex {
+    pub fn new(text: &str) -> LineIndex {
+        let mut line_starts = vec![0];
+        let mut widths = vec![];
+        for (offset, c) in text.chars().enumerate() {
+            if c == '\n' {
+                line_starts.push(offset + 1);
+            }
+            widths.push(c.len_utf16());
+        }
+        LineIndex { line_starts, widths }
+    }
+
+    pub fn position(&self, offset: usize) -> (usize, usize) {
+        let line = self.line_starts.partition_point(|start| *start <= offset) - 1;
+        let start = self.line_starts[line];
+        let end = offset.min(self.widths.len()).max(start);
+        (line, self.widths[start..end].iter().sum::<usize>() + offset - end)
+    }
+
+    pub fn offset(&self, line: usize, character: usize) -> usize {
+        let mut offset = match self.line_starts.get(line) {
+            Some(start) => *start,
+            None => return self.widths.len(),
+        };
+        let mut units = 0;
+        while units < character && offset < self.widths.len() {
+            units += self.widths[offset];
+            offset += 1;
+        }
+        offset
+    }
+
+    pub fn line_count(&self) -> usize {
+        self.line_starts.len()
+    }
+}
+
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum SymbolKind {
+    Function,
+    Variable,
+    Constant,
+    Parameter,
+    Test,
+}
+
+pub struct Symbol {
+    pub name: String,
+    pub kind: SymbolKind,
+    pub token: Token,
+    pub top_level: bool,
+}
+
+pub fn tokens(text: &str) -> Vec<Token> {
+    let mut scanner = Scanner::new(text);
+    let mut tokens = vec![];
+    loop {
+        let token = scanner.scan_token();
+        match token.token_type {
+            TokenType::EOF => break,
+            TokenType::Error(_) => continue,
+            _ => tokens.push(token),
+        }
+    }
+    tokens
+}
+
+pub fn token_at(tokens: &[Token], offset: usize) -> Option<&Token> {
+    tokens.iter().find(|token| token.start_index <= offset && offset <= token.start_index + token.length)
+}
+
+//declarations found by scanning the tokens, so they are available even when the document doesn't compile
+pub fn symbols(text: &str, tokens: &[Token]) -> Vec<Symbol> {
+    let mut symbols = vec![];
+    let mut depth = 0;
+
+    for (index, token) in tokens.iter().enumerate() {
+        let next = tokens.get(index + 1);
+        match (&token.token_type, next) {
+            (TokenType::LeftBrace, _) => depth += 1,
+            (TokenType::RightBrace, _) => depth -= 1,
+            (TokenType::Let, Some(name)) if name.token_type == TokenType::Identifier => {
+                symbols.push(symbol(text, name, SymbolKind::Variable, depth == 0));
+            }
+            (TokenType::Const, Some(name)) if name.token_type == TokenType::Identifier => {
+                symbols.push(symbol(text, name, SymbolKind::Constant, depth == 0));
+            }
+            (TokenType::Fn, Some(name)) if name.token_type == TokenType::Identifier => {
+                symbols.push(symbol(text, name, SymbolKind::Function, depth == 0));
+                let header: Vec<&Token> = tokens[index + 2..]
+                    .iter()
+                    .skip_while(|token| token.token_type == TokenType::LeftParen)
+                    .take_while(|token| token.token_type != TokenType::RightParen)
+                    .collect();
+                //identifiers after a colon are type annotations, not parameters
+                let parameters = header
+                    .iter()
+                    .enumerate()
+                    .filter(|(i, token)| token.token_type == TokenType::Identifier && (*i == 0 || header[i - 1].token_type != TokenType::Colon))
+                    .map(|(_, token)| *token);
+                for parameter in parameters {
+                    symbols.push(symbol(text, parameter, SymbolKind::Parameter, false));
+                }
+            }
+            (TokenType::Test, Some(name)) if name.token_type == TokenType::String => {
+                symbols.push(symbol(text, name, SymbolKind::Test, depth == 0));
+            }
+            _ => ()
+        }
+    }
+
+    symbols
+}
+
+fn symbol(text: &str, token: &Token, kind: SymbolKind, top_level: bool) -> Symbol {
+    Symbol {
+        name: token.get_token_string(text).trim_matches('"').to_string(),
+        kind,
+        token: token.clone(),
+        top_level,
+    }
+}
+
+//the closest declaration before the offset, or the first one after it for forward references to functions
+pub fn definition<'s>(text: &str, tokens: &[Token], symbols: &'s [Symbol], offset: usize) -> Option<&'s Symbol> {
+    let token = token_at(tokens, offset)?;
+    if token.token_type != TokenType::Identifier {
+        return None;
+    }
+
+    let name = token.get_token_string(text);
+    let candidates: Vec<&Symbol> = symbols
+        .iter()
+        .filter(|symbol| symbol.kind != SymbolKind::Test && symbol.name == name)
+        .collect();
+
+    candidates
+        .iter()
+        .rev()
+        .find(|symbol| symbol.token.start_index <= token.start_index)
+        .or_else(|| candidates.first())
+        .copied()
+}
+
+pub fn hover(text: &str, tokens: &[Token], symbols: &[Symbol], offset: usize) -> Option<String> {
+    let token = token_at(tokens, offset)?;
+    let name = token.get_token_string(text);
+
+    match token.token_type {
+        TokenType::HidKey => hid_string_to_code(&name).map(|code| format!("**{}**: HID key code `0x{:02X}`", name, code)),
+        TokenType::MouseButton => mouse_string_to_code(&name).map(|code| format!("**{}**: mouse button code `0x{:02X}`", name, code)),
+        TokenType::MediaKey => media_key(name.as_str()).map(|key| format!("**{}**: usage `0x{:02X}` on HID page `0x{:02X}`", name, key.usage, key.page)),
+        TokenType::Identifier => {
+            if let Some(native) = native_by_name(name.as_str()) {
+                return Some(format!("**{}**: native `{}`", native.name, native.signature()));
+            }
+
+            let symbol = definition(text, tokens, symbols, offset)?;
+            let (line, _) = LineIndex::new(text).position(symbol.token.start_index);
+            let kind = match symbol.kind {
+                SymbolKind::Function => "fn",
+                SymbolKind::Variable => "let",
+                SymbolKind::Constant => "const",
+                SymbolKind::Parameter => "parameter",
+                SymbolKind::Test => "test",
+            };
+            Some(format!("**{}** `{}`, declared on line {}", kind, symbol.name, line + 1))
+        }
+        _ => None
+    }
+}
+
+pub const KEYWORDS: [&str; 22] = [
+    "and", "as", "assert", "const", "else", "false", "fn", "for", "hold", "if", "import", "let", "nil", "or", "param", "print", "return", "target", "test", "true",
+    "typing", "while",
+];
@@ -0,0 +1,31 @@
+use std::io;
+use std::process::exit;
+use san_lsp::server::Server;
+use san_lsp::transport::{read_message, write_message};
+
+fn main() {
+    let stdin = io::stdin();
+    let mut reader = stdin.lock();
+    let mut writer = io::stdout();
+    let mut server = Server::new();
+
+    while !server.exit {
+        let message = match read_message(&mut reader) {
+            Ok(Some(message)) => message,
+            Ok(None) => break,
+            Err(e) => {
+                eprintln!("san-lsp: {}", e);
+                continue;
+            }
+        };
+
+        for response in server.handle(message) {
+            if let Err(e) = write_message(&mut writer, &response) {
+                eprintln!("san-lsp: {}", e);
+                exit(1);
+            }
+        }
+    }
+
+    exit(server.exit_code());
+}
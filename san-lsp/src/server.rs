@@ -0,0 +1,332 @@
+use std::collections::HashMap;
+use std::path::Path;
+use std::panic::{catch_unwind, AssertUnwindSafe};
+use serde_json::{json, Value};
+use san_common::keycodes::{HID_KEY_STRINGS, MOUSE_BUTTON_STRINGS};
+use san_common::value::FunctionType;
+use san_compiler::compiler::Compiler;
+use san_compiler::diagnostic::{Diagnostic, Severity};
+use san_compiler::fmt::format_source;
+use san_compiler::modules::ROOT_FILE;
+use san_compiler::natives::NATIVES;
+use san_compiler::params::Params;
+use crate::analysis::{definition, hover, symbols, tokens, LineIndex, SymbolKind, KEYWORDS};
+
+const METHOD_NOT_FOUND: i64 = -32601;
+const INVALID_REQUEST: i64 = -32600;
+
+//LSP CompletionItemKind and SymbolKind values
+const COMPLETION_FUNCTION: u64 = 3;
+const COMPLETION_VARIABLE: u64 = 6;
+const COMPLETION_KEYWORD: u64 = 14;
+const COMPLETION_CONSTANT: u64 = 21;
+const SYMBOL_FUNCTION: u64 = 12;
+const SYMBOL_VARIABLE: u64 = 13;
+const SYMBOL_CONSTANT: u64 = 14;
+const SYMBOL_METHOD: u64 = 6;
+
+pub struct Server {
+    documents: HashMap<String, String>,
+    shutdown: bool,
+    pub exit: bool,
+}
+
+impl Default for Server {
+    fn default() -> Self {
+        Server::new()
+    }
+}
+
+impl Server {
+    pub fn new() -> Server {
+        Server {
+            documents: HashMap::new(),
+            shutdown: false,
+            exit: false,
+        }
+    }
+
+    pub fn exit_code(&self) -> i32 {
+        if self.shutdown { 0 } else { 1 }
+    }
+
+    //handles one incoming message and returns the responses and notifications to send back
+    pub fn handle(&mut self, message: Value) -> Vec<Value> {
+        let method = message["method"].as_str().unwrap_or_default().to_string();
+        let params = &message["params"];
+        let id = message.get("id").cloned();
+
+        let result = match method.as_str() {
+            "initialize" => Ok(Server::capabilities()),
+            "shutdown" => {
+                self.shutdown = true;
+                Ok(Value::Null)
+            }
+            "exit" => {
+                self.exit = true;
+                return vec![];
+            }
+            "initialized" | "$/cancelRequest" | "workspace/didChangeConfiguration" => return vec![],
+            "textDocument/didOpen" => {
+                let uri = params["textDocument"]["uri"].as_str().unwrap_or_default().to_string();
+                let text = params["textDocument"]["text"].as_str().unwrap_or_default().to_string();
+                return self.update(uri, text);
+            }
+            "textDocument/didChange" => {
+                let uri = params["textDocument"]["uri"].as_str().unwrap_or_default().to_string();
+                //only full document sync is advertised, so the last change holds the whole text
+                let text = params["contentChanges"]
+                    .as_array()
+                    .and_then(|changes| changes.last())
+                    .and_then(|change| change["text"].as_str())
+                    .unwrap_or_default()
+                    .to_string();
+                return self.update(uri, text);
+            }
+            "textDocument/didClose" => {
+                let uri = params["textDocument"]["uri"].as_str().unwrap_or_default().to_string();
+                self.documents.remove(&uri);
+                return vec![Server::publish_diagnostics(uri.as_str(), vec![])];
+            }
+            "textDocument/completion" => Ok(self.completion(params)),
+            "textDocument/hover" => Ok(self.hover(params)),
+            "textDocument/definition" => Ok(self.definition(params)),
+            "textDocument/documentSymbol" => Ok(self.document_symbols(params)),
+            "textDocument/formatting" => Ok(self.formatting(params)),
+            _ if id.is_none() => return vec![],
+            _ => Err((METHOD_NOT_FOUND, format!("Unknown method '{}'", method))),
+        };
+
+        let id = match id {
+            Some(id) => id,
+            None => return vec![Server::error(Value::Null, INVALID_REQUEST, format!("Request '{}' has no id", method))],
+        };
+
+        match result {
+            Ok(result) => vec![json!({ "jsonrpc": "2.0", "id": id, "result": result })],
+            Err((code, message)) => vec![Server::error(id, code, message)],
+        }
+    }
+
+    fn capabilities() -> Value {
+        json!({
+            "capabilities": {
+                "textDocumentSync": 1,
+                "completionProvider": { "triggerCharacters": [] },
+                "hoverProvider": true,
+                "definitionProvider": true,
+                "documentSymbolProvider": true,
+                "documentFormattingProvider": true,
+            },
+            "serverInfo": { "name": "san-lsp", "version": env!("CARGO_PKG_VERSION") },
+        })
+    }
+
+    fn error(id: Value, code: i64, message: String) -> Value {
+        json!({ "jsonrpc": "2.0", "id": id, "error": { "code": code, "message": message } })
+    }
+
+    fn update(&mut self, uri: String, text: String) -> Vec<Value> {
+        let diagnostics = Server::diagnostics(uri.as_str(), text.as_str());
+        self.documents.insert(uri.clone(), text);
+        vec![Server::publish_diagnostics(uri.as_str(), diagnostics)]
+    }
+
+    fn publish_diagnostics(uri: &str, diagnostics: Vec<Value>) -> Value {
+        json!({
+            "jsonrpc": "2.0",
+            "method": "textDocument/publishDiagnostics",
+            "params": { "uri": uri, "diagnostics": diagnostics },
+        })
+    }
+
+    fn diagnostics(uri: &str, text: &str) -> Vec<Value> {
+        //a compiler panic must not take the whole editor session down with it
+        let compiled = catch_unwind(AssertUnwindSafe(|| {
+            let mut compiler = Compiler::new(text, FunctionType::Script);
+            compiler.set_quiet(true);
+            //imports of documents on disk resolve next to them, params and the target come from their config.toml
+            if let Some(path) = uri.strip_prefix("file://") {
+                compiler.set_path(Path::new(path));
+                if let Ok(params) = Params::from_config(Path::new(path)) {
+                    compiler.set_params(params);
+                }
+            }
+            compiler.compile();
+            compiler.diagnostics().to_vec()
+        }));
+
+        let index = LineIndex::new(text);
+        match compiled {
+            //problems inside imported files show up as an error on their import
+            Ok(diagnostics) => diagnostics
+                .iter()
+                .filter(|diagnostic| diagnostic.file == ROOT_FILE)
+                .map(|diagnostic| Server::diagnostic(&index, diagnostic))
+                .collect(),
+            Err(_) => vec![json!({
+                "range": Server::range(&index, 0, 0),
+                "severity": 1,
+                "source": "sanc",
+                "message": "The compiler crashed while analyzing this document",
+            })],
+        }
+    }
+
+    fn diagnostic(index: &LineIndex, diagnostic: &Diagnostic) -> Value {
+        let range = match diagnostic.start_index {
+            Some(start) => Server::range(index, start, start + diagnostic.length),
+            None => {
+                let line = diagnostic.line.saturating_sub(1);
+                json!({
+                    "start": { "line": line, "character": 0 },
+                    "end": { "line": line + 1, "character": 0 },
+                })
+            }
+        };
+
+        json!({
+            "range": range,
+            "severity": if diagnostic.severity == Severity::Error { 1 } else { 2 },
+            "source": "sanc",
+            "message": diagnostic.message,
+        })
+    }
+
+    fn range(index: &LineIndex, start: usize, end: usize) -> Value {
+        let (start_line, start_character) = index.position(start);
+        let (end_line, end_character) = index.position(end);
+        json!({
+            "start": { "line": start_line, "character": start_character },
+            "end": { "line": end_line, "character": end_character },
+        })
+    }
+
+    //the document text and the char offset of the requested position
+    fn document_at(&self, params: &Value) -> Option<(&String, usize)> {
+        let text = self.documents.get(params["textDocument"]["uri"].as_str()?)?;
+        let line = params["position"]["line"].as_u64()? as usize;
+        let character = params["position"]["character"].as_u64()? as usize;
+        Some((text, LineIndex::new(text).offset(line, character)))
+    }
+
+    fn completion(&self, params: &Value) -> Value {
+        let mut items = vec![];
+
+        for key in HID_KEY_STRINGS.iter() {
+            items.push(json!({ "label": key, "kind": COMPLETION_CONSTANT, "detail": "HID key" }));
+        }
+        for button in MOUSE_BUTTON_STRINGS.iter() {
+            items.push(json!({ "label": button, "kind": COMPLETION_CONSTANT, "detail": "Mouse button" }));
+        }
+        for native in NATIVES.iter().filter(|native| !native.name.starts_with("__")) {
+            items.push(json!({ "label": native.name, "kind": COMPLETION_FUNCTION, "detail": native.signature().to_string() }));
+        }
+        for keyword in KEYWORDS {
+            items.push(json!({ "label": keyword, "kind": COMPLETION_KEYWORD }));
+        }
+
+        if let Some(text) = self.documents.get(params["textDocument"]["uri"].as_str().unwrap_or_default()) {
+            let tokens = tokens(text);
+            let mut seen = vec![];
+            for symbol in symbols(text, &tokens) {
+                if symbol.kind == SymbolKind::Test || seen.contains(&symbol.name) {
+                    continue;
+                }
+                let kind = match symbol.kind {
+                    SymbolKind::Function => COMPLETION_FUNCTION,
+                    SymbolKind::Constant => COMPLETION_CONSTANT,
+                    _ => COMPLETION_VARIABLE,
+                };
+                items.push(json!({ "label": symbol.name, "kind": kind }));
+                seen.push(symbol.name);
+            }
+        }
+
+        json!({ "isIncomplete": false, "items": items })
+    }
+
+    fn hover(&self, params: &Value) -> Value {
+        let (text, offset) = match self.document_at(params) {
+            Some(document) => document,
+            None => return Value::Null,
+        };
+
+        let tokens = tokens(text);
+        let symbols = symbols(text, &tokens);
+        match hover(text, &tokens, &symbols, offset) {
+            Some(contents) => json!({ "contents": { "kind": "markdown", "value": contents } }),
+            None => Value::Null,
+        }
+    }
+
+    fn definition(&self, params: &Value) -> Value {
+        let (text, offset) = match self.document_at(params) {
+            Some(document) => document,
+            None => return Value::Null,
+        };
+
+        let tokens = tokens(text);
+        let symbols = symbols(text, &tokens);
+        match definition(text, &tokens, &symbols, offset) {
+            Some(symbol) => json!({
+                "uri": params["textDocument"]["uri"],
+                "range": Server::range(&LineIndex::new(text), symbol.token.start_index, symbol.token.start_index + symbol.token.length),
+            }),
+            None => Value::Null,
+        }
+    }
+
+    fn document_symbols(&self, params: &Value) -> Value {
+        let text = match self.documents.get(params["textDocument"]["uri"].as_str().unwrap_or_default()) {
+            Some(text) => text,
+            None => return Value::Null,
+        };
+
+        let index = LineIndex::new(text);
+        let tokens = tokens(text);
+        let symbols: Vec<Value> = symbols(text, &tokens)
+            .iter()
+            .filter(|symbol| symbol.top_level)
+            .map(|symbol| {
+                let kind = match symbol.kind {
+                    SymbolKind::Function => SYMBOL_FUNCTION,
+                    SymbolKind::Test => SYMBOL_METHOD,
+                    SymbolKind::Constant => SYMBOL_CONSTANT,
+                    _ => SYMBOL_VARIABLE,
+                };
+                json!({
+                    "name": symbol.name,
+                    "kind": kind,
+                    "location": {
+                        "uri": params["textDocument"]["uri"],
+                        "range": Server::range(&index, symbol.token.start_index, symbol.token.start_index + symbol.token.length),
+                    },
+                })
+            })
+            .collect();
+
+        Value::Array(symbols)
+    }
+
+    fn formatting(&self, params: &Value) -> Value {
+        let text = match self.documents.get(params["textDocument"]["uri"].as_str().unwrap_or_default()) {
+            Some(text) => text,
+            None => return Value::Null,
+        };
+
+        match format_source(text) {
+            Ok(formatted) if formatted != *text => {
+                let index = LineIndex::new(text);
+                json!([{
+                    "range": {
+                        "start": { "line": 0, "character": 0 },
+                        "end": { "line": index.line_count(), "character": 0 },
+                    },
+                    "newText": formatted,
+                }])
+            }
+            _ => json!([]),
+        }
+    }
+}
@@ -0,0 +1,166 @@
+use std::io::Cursor;
+use serde_json::{json, Value};
+use san_lsp::server::Server;
+use san_lsp::transport::{read_message, write_message};
+
+const URI: &str = "file:///payload.san";
+
+//drives the server through the same framing the stdio transport uses
+struct Client {
+    server: Server,
+    next_id: u64,
+}
+
+impl Client {
+    fn new() -> Client {
+        let mut client = Client { server: Server::new(), next_id: 0 };
+        let result = client.request("initialize", json!({ "capabilities": {} }));
+        assert_eq!(result["capabilities"]["hoverProvider"], json!(true));
+        client.notify("initialized", json!({}));
+        client
+    }
+
+    fn send(&mut self, message: Value) -> Vec<Value> {
+        let mut framed = vec![];
+        write_message(&mut framed, &message).unwrap();
+        let message = read_message(&mut Cursor::new(framed)).unwrap().unwrap();
+
+        let mut responses = vec![];
+        for response in self.server.handle(message) {
+            let mut framed = vec![];
+            write_message(&mut framed, &response).unwrap();
+            responses.push(read_message(&mut Cursor::new(framed)).unwrap().unwrap());
+        }
+        responses
+    }
+
+    fn request(&mut self, method: &str, params: Value) -> Value {
+        self.next_id += 1;
+        let id = self.next_id;
+        let responses = self.send(json!({ "jsonrpc": "2.0", "id": id, "method": method, "params": params }));
+        let response = responses.into_iter().find(|response| response["id"] == json!(id)).expect("no response");
+        assert!(response.get("error").is_none(), "{} failed: {}", method, response);
+        response["result"].clone()
+    }
+
+    fn notify(&mut self, method: &str, params: Value) -> Vec<Value> {
+        self.send(json!({ "jsonrpc": "2.0", "method": method, "params": params }))
+    }
+
+    fn open(&mut self, text: &str) -> Vec<Value> {
+        let notifications = self.notify("textDocument/didOpen", json!({
+            "textDocument": { "uri": URI, "languageId": "sanscript", "version": 1, "text": text }
+        }));
+        assert_eq!(notifications[0]["method"], "textDocument/publishDiagnostics");
+        notifications[0]["params"]["diagnostics"].as_array().unwrap().clone()
+    }
+
+    fn at(&mut self, method: &str, line: u64, character: u64) -> Value {
+        self.request(method, json!({
+            "textDocument": { "uri": URI },
+            "position": { "line": line, "character": character }
+        }))
+    }
+}
+
+#[test]
+fn valid_document_has_no_diagnostics() {
+    let mut client = Client::new();
+    assert!(client.open("let key = ENTER;\ninject_keys(key);\n").is_empty());
+}
+
+#[test]
+fn errors_are_published_with_their_line() {
+    let mut client = Client::new();
+    let diagnostics = client.open("let key = ENTER;\ninject_keys(key);\nlet = 5;\n");
+    assert_eq!(diagnostics.len(), 1);
+    assert_eq!(diagnostics[0]["range"]["start"]["line"], json!(2));
+    assert_eq!(diagnostics[0]["severity"], json!(1));
+}
+
+#[test]
+fn changes_republish_diagnostics() {
+    let mut client = Client::new();
+    assert_eq!(client.open("let = 5;\n").len(), 1);
+    let notifications = client.notify("textDocument/didChange", json!({
+        "textDocument": { "uri": URI, "version": 2 },
+        "contentChanges": [{ "text": "let a = 5;\nprint a;\n" }]
+    }));
+    assert!(notifications[0]["params"]["diagnostics"].as_array().unwrap().is_empty());
+}
+
+#[test]
+fn warnings_are_published_with_their_severity() {
+    let mut client = Client::new();
+    let diagnostics = client.open("let a = 5;\n");
+    assert_eq!(diagnostics.len(), 1);
+    assert_eq!(diagnostics[0]["severity"], json!(2));
+    assert!(diagnostics[0]["message"].as_str().unwrap().contains("Unused variable 'a'"));
+}
+
+#[test]
+fn completion_offers_keys_buttons_and_natives() {
+    let mut client = Client::new();
+    client.open("fn payload() {}\n");
+    let result = client.at("textDocument/completion", 1, 0);
+    let labels: Vec<&str> = result["items"].as_array().unwrap().iter().map(|item| item["label"].as_str().unwrap()).collect();
+    for label in ["ENTER", "LEFT_CLICK", "inject_keys", "payload"] {
+        assert!(labels.contains(&label), "missing completion {}", label);
+    }
+    assert!(!labels.contains(&"__assert_fail"));
+}
+
+#[test]
+fn hover_shows_hid_code() {
+    let mut client = Client::new();
+    client.open("inject_keys(ENTER);\n");
+    let result = client.at("textDocument/hover", 0, 14);
+    assert!(result["contents"]["value"].as_str().unwrap().contains("0x28"));
+}
+
+#[test]
+fn definition_finds_let_and_fn() {
+    let mut client = Client::new();
+    client.open("let key = ENTER;\nfn press(k) {\n    inject_keys(k);\n}\npress(key);\n");
+
+    let result = client.at("textDocument/definition", 4, 1);
+    assert_eq!(result["range"]["start"], json!({ "line": 1, "character": 3 }));
+
+    let result = client.at("textDocument/definition", 4, 7);
+    assert_eq!(result["range"]["start"], json!({ "line": 0, "character": 4 }));
+}
+
+#[test]
+fn positions_count_utf16_code_units() {
+    let mut client = Client::new();
+    client.open("let s = \"\u{1F600}\"; let key = ENTER;\ninject_keys(key);\n");
+    let result = client.at("textDocument/definition", 1, 13);
+    assert_eq!(result["range"]["start"], json!({ "line": 0, "character": 18 }));
+}
+
+#[test]
+fn document_symbols_list_top_level_declarations() {
+    let mut client = Client::new();
+    client.open("let a = 1;\nfn f(x) {\n    let b = x;\n}\n");
+    let result = client.request("textDocument/documentSymbol", json!({ "textDocument": { "uri": URI } }));
+    let names: Vec<&str> = result.as_array().unwrap().iter().map(|symbol| symbol["name"].as_str().unwrap()).collect();
+    assert_eq!(names, vec!["a", "f"]);
+}
+
+#[test]
+fn formatting_replaces_the_document() {
+    let mut client = Client::new();
+    client.open("let a=1;\n");
+    let result = client.request("textDocument/formatting", json!({
+        "textDocument": { "uri": URI },
+        "options": { "tabSize": 4, "insertSpaces": true }
+    }));
+    assert_eq!(result[0]["newText"], json!("let a = 1;\n"));
+}
+
+#[test]
+fn unknown_requests_are_rejected() {
+    let mut client = Client::new();
+    let responses = client.send(json!({ "jsonrpc": "2.0", "id": 99, "method": "textDocument/rename", "params": {} }));
+    assert_eq!(responses[0]["error"]["code"], json!(-32601));
+}